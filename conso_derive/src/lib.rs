@@ -0,0 +1,168 @@
+//! Derive macros for `conso`. These are re-exported from `conso` when its `derive` feature is enabled.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, LitStr};
+
+/// Derives `conso::ConsoEnum` for an enum with only unit variants, letting the variants be picked
+/// by their lowercased names. `#[conso(rename = "...")]` on a variant adds another name it can be
+/// picked by, while the lowercased name keeps working. Both show up in the help.
+///
+/// ```
+/// use conso::ConsoEnum;
+///
+/// #[derive(ConsoEnum, Clone, Copy, Debug, PartialEq)]
+/// enum Direction {
+///     North,
+///     South,
+///     #[conso(rename = "e")]
+///     East,
+///     West,
+/// }
+///
+/// let mut directions = Vec::new();
+/// for name in ["e", "east", "west"] {
+///     conso::parse(&["go", name], |ctx| {
+///         ctx.command("go")
+///             .constrained_arg(Direction::arg())
+///             .run(|&d| directions.push(d));
+///     });
+/// }
+/// assert_eq!(directions, [Direction::East, Direction::East, Direction::West]);
+///
+/// let help = conso::render_help(|ctx| {
+///     ctx.command("go").constrained_arg(Direction::arg()).run(|_| {});
+/// });
+/// assert!(help.contains("[north|south|east|e|west]"));
+/// ```
+#[proc_macro_derive(ConsoEnum, attributes(conso))]
+pub fn derive_conso_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match conso_enum(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn conso_enum(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(&input.ident, "ConsoEnum can only be derived for enums"));
+    };
+
+    let mut names = Vec::new();
+    let mut idents = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(variant, "ConsoEnum variants can't have fields"));
+        }
+
+        let mut variant_names = vec![variant.ident.to_string().to_lowercase()];
+        for attr in variant.attrs.iter().filter(|attr| attr.path().is_ident("conso")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    variant_names.push(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown conso attribute"))
+                }
+            })?;
+        }
+
+        names.push(variant_names);
+        idents.push(&variant.ident);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::conso::ConsoEnum for #ident #ty_generics #where_clause {
+            const NAMES: &'static [&'static str] = &[#(#(#names),*),*];
+
+            fn from_name(name: &str) -> ::core::option::Option<Self> {
+                match name {
+                    #(#(#names)|* => ::core::option::Option::Some(Self::#idents),)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+    })
+}
+
+/// Derives `conso::Arg` for a struct with named fields, parsing the fields in the order they're
+/// declared. Every field type has to implement `Arg`, and shows up in the help labeled with the
+/// name of the field, like `qty=<unsigned integer>`.
+///
+/// ```
+/// use conso::ConsoArgs;
+///
+/// #[derive(ConsoArgs)]
+/// struct Item {
+///     name: String,
+///     qty: u32,
+///     price: u32,
+/// }
+///
+/// let mut added = None;
+/// conso::parse(&["add", "apple", "3", "50"], |ctx| {
+///     ctx.command("add")
+///         .arg::<Item>()
+///         .run(|item| added = Some((item.name.clone(), item.qty, item.price)));
+/// });
+/// assert_eq!(added, Some((String::from("apple"), 3, 50)));
+///
+/// let help = conso::render_help(|ctx| {
+///     ctx.command("add").arg::<Item>().run(|_| {});
+/// });
+/// assert!(help.contains("add name=<string> qty=<unsigned integer> price=<unsigned integer>"));
+/// ```
+#[proc_macro_derive(ConsoArgs)]
+pub fn derive_conso_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match conso_args(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn conso_args(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(&input.ident, "ConsoArgs can only be derived for structs"));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(&input.ident, "ConsoArgs can only be derived for structs with named fields"));
+    };
+
+    let idents = fields.named.iter().map(|field| field.ident.as_ref().unwrap()).collect::<Vec<_>>();
+    let names = idents.iter().map(|ident| ident.to_string()).collect::<Vec<_>>();
+    let types = fields.named.iter().map(|field| &field.ty).collect::<Vec<_>>();
+
+    // The lifetime of the input is added to the generics of the struct itself
+    let mut generics = input.generics.clone();
+    generics.params.insert(0, parse_quote!('conso_input));
+    {
+        let where_clause = generics.make_where_clause();
+        for ty in &types {
+            where_clause.predicates.push(parse_quote!(#ty: ::conso::Arg<'conso_input>));
+        }
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let ident = &input.ident;
+    Ok(quote! {
+        impl #impl_generics ::conso::Arg<'conso_input> for #ident #ty_generics #where_clause {
+            fn help(fmt: &mut ::conso::HelpFmt) {
+                #(
+                    ::conso::ConstrainedArg::help(&::conso::labeled(#names, ::conso::unconstrained::<#types>()), fmt);
+                )*
+            }
+
+            fn parse(input: &mut ::conso::Segments<'conso_input>) -> ::core::option::Option<Self> {
+                #(
+                    let #idents = <#types as ::conso::Arg<'conso_input>>::parse(input)?;
+                )*
+                ::core::option::Option::Some(Self { #(#idents),* })
+            }
+        }
+    })
+}
@@ -17,39 +17,24 @@ fn main() {
         ],
     };
 
-    conso::user_loop(|ctx, control_flow| {
-        ctx.command("w")
-            .description("Move forward")
-            .run(|| {
-                game.player_y += 1;
-                control_flow.quit(());
-            });
-
-        ctx.command("s")
-            .description("Move backwards")
-            .run(|| {
-                game.player_y -= 1;
-                control_flow.quit(());
-            });
-
-        ctx.command("a")
-            .description("Move left")
-            .run(|| {
-                game.player_x -= 1;
-                control_flow.quit(());
-            });
-
-        ctx.command("d")
-            .description("Move right")
-            .run(|| {
-                game.player_x += 1;
+    conso::user_loop_stateful(&mut game, |game, ctx, control_flow| {
+        ctx.group("Movement", |ctx| {
+            ctx.commands([
+                ("w", "Move forward", (0, 1)),
+                ("s", "Move backwards", (0, -1)),
+                ("a", "Move left", (-1, 0)),
+                ("d", "Move right", (1, 0)),
+            ], |(x, y)| {
+                game.player_x += x;
+                game.player_y += y;
                 control_flow.quit(());
             });
+        });
 
         ctx.command("inv")
             .description("Manage inventory")
             .sub_commands(|ctx| {
-                manage_inventory(ctx, &mut game);
+                manage_inventory(ctx, game);
             });
     });
 }
@@ -64,23 +49,22 @@ fn manage_inventory(ctx: &mut conso::Ctx, game: &mut Game) {
         });
 
     ctx.command("discard")
-        .description("Discard an item in your inventory")
-        .sub_commands(|ctx| {
-            let mut to_discard = None;
-            for (i, item) in game.inventory.iter().enumerate() {
-                ctx.command(format!("{}", item))
-                    .run(|| {
-                        to_discard = Some(i);
-                    });
-            }
-            if let Some(to_discard) = to_discard {
-                game.inventory.remove(to_discard);
+        .description_with(|fmt| {
+            fmt.push_paragraph("Discard an item in your inventory");
+            fmt.line_break();
+            fmt.push_paragraph(&format!("You're holding: {}", game.inventory.join(", ")));
+        })
+        .constrained_arg(conso::choices(game.inventory.iter().cloned().zip(0..)))
+        .run(|&i| {
+            if conso::confirm(&format!("Really discard the {}?", game.inventory[i])) {
+                game.inventory.remove(i);
                 println!("Discarded item!");
             }
         });
 
     ctx.command("add")
         .description("Adds an item to your inventory")
+        .example("inv add sword")
         .arg::<String>()
         .run(|name| {
             println!("Added item to the inventory!");
@@ -16,7 +16,7 @@ fn main() {
                         ctx.command("quit").run(|| control_flow.quit(()));
                     });
             })
-            .run(|| {
+            .default(|| {
                 println!("Hello, world!");
             });
 
@@ -1,109 +1,1323 @@
 #![doc = include_str!("../README.md")]
 
-use std::io::Write;
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::io::{BufRead, IsTerminal, Write};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU128, NonZeroUsize};
+use std::num::{NonZeroI8, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI128, NonZeroIsize};
+use std::num::{Saturating, Wrapping};
 use std::slice::Iter;
-use std::ops::Range;
+use std::ops::{Range, RangeInclusive};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
-/// Runs the parser on the command line arguments
+/// Runs the parser on the command line arguments. Input is always text, so an argument that
+/// isn't valid UTF-8 is reported as an error, like any other mistake in the input.
 pub fn args(handler: impl FnMut(&mut Ctx<'_, '_>)) {
-    // HACK: It might be pretty bad to do skip(1) here actually.... it doesn't feel good..
-    let args: Vec<String> = std::env::args().skip(1).collect();
-    let args: Vec<&str> = args.iter().map(|v| &**v).collect();
-    parse(&args, handler);
+    args_status(handler);
 }
 
-pub fn parse(segments: &[&str], mut handler: impl FnMut(&mut Ctx<'_, '_>)) {
-    match &*segments {
-        ["help"] => {
-            let mut help = HelpFmt::default();
-            let mut ctx = Ctx(CtxInner::BuildHelpInfo {
-                help: &mut help,
-            });
-            handler(&mut ctx);
-            help.line_break();
+/// Like `args`, but with `options` changing how the input is parsed.
+///
+/// ```no_run
+/// conso::args_with(&conso::Options::default().help_keywords(&["hilfe"]), |ctx| {
+///     ctx.command("grüßen")
+///         .run(|| {
+///             println!("Hallo Welt!");
+///         });
+/// });
+/// ```
+pub fn args_with(options: &Options, handler: impl FnMut(&mut Ctx<'_, '_>)) {
+    report_args(command_line(), options, handler);
+}
+
+/// Like `args`, but parses `args` instead of the command line arguments. Nothing is skipped, so
+/// unlike with `args` the first one is parsed too. Useful when the arguments come from somewhere
+/// else, like a parent process. The arguments can be `String`s, or `OsString`s that are checked
+/// to be valid UTF-8 like in `args`.
+///
+/// ```
+/// let mut greeted = false;
+/// conso::args_from(vec![String::from("greet")], |ctx| {
+///     ctx.command("greet")
+///         .run(|| greeted = true);
+/// });
+/// assert!(greeted);
+///
+/// // The first argument picks the command, and the rest are its arguments
+/// let mut copied = None;
+/// conso::args_from(["copy", "a.txt", "b.txt"], |ctx| {
+///     ctx.command("copy")
+///         .arg::<String>()
+///         .arg::<String>()
+///         .run(|(from, to)| copied = Some((from.clone(), to.clone())));
+/// });
+/// assert_eq!(copied, Some((String::from("a.txt"), String::from("b.txt"))));
+/// ```
+///
+/// ```
+/// # #[cfg(unix)] {
+/// use std::os::unix::ffi::OsStringExt;
+///
+/// let mut ran = false;
+/// conso::args_from([std::ffi::OsString::from_vec(vec![b'g', 0xff])], |ctx| {
+///     ctx.fallback(|_| ran = true);
+/// });
+/// assert!(!ran);
+/// # }
+/// ```
+pub fn args_from(args: impl IntoIterator<Item = impl Into<OsString>>, handler: impl FnMut(&mut Ctx<'_, '_>)) {
+    report_args(args, &Options::default(), handler);
+}
+
+/// Like `args`, but arguments that aren't valid UTF-8 are still parsed, with the invalid parts
+/// replaced by `�` and a warning printed, instead of being reported as an error. Useful when
+/// some arguments are only shown to the user, like file names.
+///
+/// ```no_run
+/// conso::args_os(|ctx| {
+///     ctx.command("show")
+///         .arg::<String>()
+///         .run(|file| println!("Showing {}", file));
+/// });
+/// ```
+pub fn args_os(handler: impl FnMut(&mut Ctx<'_, '_>)) {
+    let args = command_line().enumerate().map(|(i, arg)| {
+        let text = arg.to_string_lossy().into_owned();
+        if arg.to_str().is_none() {
+            eprintln!("Warning: argument #{} isn't valid UTF-8, it's read as {}", i + 1, text);
         }
-        ["help", segments @ ..] => {
-            let mut help = HelpFmt::default();
-            let mut finished = None;
-            Command::<()>(DataCommand(CommandInner::BuildSubHelpInfo {
-                input: Segments {
-                    original: segments,
-                    iter: segments.iter(),
-                    depth: 0,
-                },
-                help: &mut help,
-                finished: &mut finished,
-            })).sub_commands(handler);
-            help.line_break();
-            if let Some(finished) = finished {
-                print_finished_state(&segments, finished);
-            }
+        text
+    });
+    report_args(args, &Options::default(), handler);
+}
+
+/// Like `args`, but returns an exit code for the process; `1` if there was an error in the
+/// arguments, and `0` otherwise.
+///
+/// ```no_run
+/// let status = conso::args_status(|ctx| {
+///     ctx.command("greet")
+///         .run(|| {
+///             println!("Hello world!");
+///         });
+/// });
+/// std::process::exit(status);
+/// ```
+pub fn args_status(handler: impl FnMut(&mut Ctx<'_, '_>)) -> i32 {
+    report_args(command_line(), &Options::default(), handler)
+}
+
+/// The command line arguments, without the first one, since that's the path of the program.
+fn command_line() -> impl Iterator<Item = OsString> {
+    std::env::args_os().skip(1)
+}
+
+/// Parses `args`, returning the exit code for the process.
+fn report_args(args: impl IntoIterator<Item = impl Into<OsString>>, options: &Options, handler: impl FnMut(&mut Ctx<'_, '_>)) -> i32 {
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let text: Vec<String> = args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    let segments: Vec<&str> = text.iter().map(|v| &**v).collect();
+
+    if let Some(index) = args.iter().position(|arg| arg.to_str().is_none()) {
+        let error = ParseError::new(&segments, index as u32, String::from("Argument isn't valid UTF-8"), None);
+        std::io::stdout().write_all(error.to_string().as_bytes()).unwrap();
+        return 1;
+    }
+    match parse_and_report(&segments, None, options, &mut std::io::stdout(), handler) {
+        Ok(true) => 0,
+        Ok(false) | Err(_) => 1,
+    }
+}
+
+pub fn parse(segments: &[&str], handler: impl FnMut(&mut Ctx<'_, '_>)) {
+    parse_and_report(segments, None, &Options::default(), &mut std::io::stdout(), handler).unwrap();
+}
+
+/// Like `parse`, but with `options` changing how the input is parsed.
+///
+/// ```
+/// let options = conso::Options::default().help_keywords(&["aide", "hilfe"]);
+/// conso::parse_with(&["aide"], &options, |ctx| {
+///     ctx.command("saluer")
+///         .run(|| {
+///             println!("Bonjour le monde!");
+///         });
+/// });
+/// ```
+pub fn parse_with(segments: &[&str], options: &Options, handler: impl FnMut(&mut Ctx<'_, '_>)) {
+    parse_and_report(segments, None, options, &mut std::io::stdout(), handler).unwrap();
+}
+
+/// Settings for how input is parsed, for the functions ending in `_with`. Made with
+/// `Options::default()` and changed with the methods on it.
+#[derive(Debug, Clone)]
+pub struct Options {
+    help_keywords: &'static [&'static str],
+    pager: bool,
+    continuation_prompt: &'static str,
+}
+
+impl Options {
+    /// The words that show the help information when given as the first or last segment, like
+    /// `help inv` or `inv help`. Useful for command lines that aren't in English. As the last
+    /// segment, they only show the help if the input doesn't work with them as an argument.
+    ///
+    /// ```
+    /// fn tree(ctx: &mut conso::Ctx) {
+    ///     ctx.command("inv").sub_commands(|ctx| {
+    ///         ctx.command("list").run(|| {});
+    ///     });
+    ///     ctx.command("say")
+    ///         .arg::<String>()
+    ///         .run(|message| println!("{}", message));
+    /// }
+    /// let leading = conso::test::run(&["help", "inv"], tree);
+    /// let trailing = conso::test::run(&["inv", "help"], tree);
+    /// assert_eq!(leading.output, trailing.output);
+    /// assert!(trailing.output.contains("list"));
+    ///
+    /// let said = conso::test::run(&["say", "help"], tree);
+    /// assert_eq!(said.state, conso::FinishedState::Okay { path: vec![String::from("say")] });
+    /// ```
+    pub fn help_keywords(mut self, keywords: &'static [&'static str]) -> Self {
+        self.help_keywords = keywords;
+        self
+    }
+
+    /// Shows the help information through a pager, so that long help doesn't scroll off the
+    /// screen. The pager is `$PAGER`, or `less` if that isn't set. Only used when the output is a
+    /// terminal, and the help is printed like normal if the pager couldn't be started.
+    pub fn pager(mut self, pager: bool) -> Self {
+        self.pager = pager;
+        self
+    }
+
+    /// The prompt shown in `user_loop_with` when a quote wasn't closed, and the command goes on
+    /// to the next line.
+    pub fn continuation_prompt(mut self, prompt: &'static str) -> Self {
+        self.continuation_prompt = prompt;
+        self
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { help_keywords: &["help"], pager: false, continuation_prompt: "> " }
+    }
+}
+
+/// Like `parse`, but returns the segments that were matched by the commands leading up to the
+/// one that ran, not including any arguments. Returns `None` if no command ran, e.g. because
+/// of an error. Useful for logging which commands are used.
+///
+/// ```
+/// let path = conso::parse_matched(&["inv", "add", "sword"], |ctx| {
+///     ctx.command("inv").sub_commands(|ctx| {
+///         ctx.command("add")
+///             .arg::<String>()
+///             .run(|_| {});
+///     });
+/// });
+/// assert_eq!(path, Some(vec![String::from("inv"), String::from("add")]));
+/// ```
+pub fn parse_matched(segments: &[&str], handler: impl FnMut(&mut Ctx<'_, '_>)) -> Option<Vec<String>> {
+    match parse_with_state(segments, None, &Options::default(), &mut std::io::stdout(), handler).unwrap() {
+        FinishedState::Okay { path } => Some(path),
+        _ => None,
+    }
+}
+
+/// Parses and writes any errors or help to `out`, returning false if there were errors.
+/// `line` is the line `segments` were split from, if there was one.
+fn parse_and_report(segments: &[&str], line: Option<&str>, options: &Options, out: &mut impl Write, handler: impl FnMut(&mut Ctx<'_, '_>)) -> std::io::Result<bool> {
+    Ok(!matches!(parse_with_state(segments, line, options, out, handler)?, FinishedState::Error { .. }))
+}
+
+/// The name of the flag or option `segment` starts, if it starts one. A `-` followed by a digit is
+/// always a value, like a negative number, and never a flag.
+fn flag_name(segment: &str) -> Option<&str> {
+    if segment.strip_prefix('-').is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())) {
+        return None;
+    }
+    segment.strip_prefix("--")
+}
+
+/// Like `parse`, but errors are given back instead of printed, so they can be shown in whatever
+/// way fits the program. The help is still printed as usual, and the outer error is from
+/// printing it.
+///
+/// ```
+/// let result = conso::try_parse(&["greet", "bob"], |ctx| {
+///     ctx.command("greet")
+///         .run(|| println!("Hello world!"));
+/// });
+/// let error = result.unwrap().unwrap_err();
+/// assert_eq!(error.message, "Excess arguments passed");
+/// eprintln!("my app: {}", error.message);
+/// ```
+pub fn try_parse(segments: &[&str], handler: impl FnMut(&mut Ctx<'_, '_>)) -> std::io::Result<Result<(), ParseError>> {
+    let (finished, segments, help) = parse_quietly(segments, None, &Options::default(), handler);
+    std::io::stdout().write_all(help.as_bytes())?;
+    Ok(finished_result(finished, &segments))
+}
+
+/// Turns how parsing `segments` finished into whether there was an error.
+fn finished_result(finished: FinishedState, segments: &[&str]) -> Result<(), ParseError> {
+    match finished {
+        FinishedState::Error { depth, message, help } => Err(ParseError::new(segments, depth, message, help)),
+        FinishedState::Okay { .. } | FinishedState::Help => Ok(()),
+    }
+}
+
+/// Runs every line in `lines` as a command, like `user_loop` would, and gives back how each of
+/// them went. Blank lines and lines starting with `#` are skipped. Quotes work like in the loop,
+/// except that a quote left open is an error. If `stop_on_error` is set, no more lines are run
+/// after the first one with an error. Help is printed as usual.
+///
+/// The lines can come straight from `BufRead::lines`. If reading a line or printing fails, the
+/// script stops with that error.
+///
+/// ```
+/// use std::io::BufRead;
+///
+/// let script = "add 1\n# a comment\nadd x\nadd 2";
+/// let mut total = 0;
+/// let results = conso::run_script(script.as_bytes().lines(), false, |ctx| {
+///     ctx.command("add")
+///         .arg::<u32>()
+///         .run(|&n| total += n);
+/// }).unwrap();
+/// assert_eq!(total, 3);
+/// assert_eq!(results.len(), 3);
+/// assert_eq!(results[1].line_number, 3);
+/// assert!(results[1].outcome.is_err());
+///
+/// let mut notes = Vec::new();
+/// let results = conso::run_script(["note \"buy milk\"", "note \"walk"].map(|line| Ok(String::from(line))), false, |ctx| {
+///     ctx.command("note")
+///         .arg::<String>()
+///         .run(|note| notes.push(note.clone()));
+/// }).unwrap();
+/// assert_eq!(notes, ["buy milk"]);
+/// assert_eq!(results[1].outcome.as_ref().unwrap_err().message, "Unclosed quote");
+/// ```
+pub fn run_script(lines: impl IntoIterator<Item = std::io::Result<String>>, stop_on_error: bool, mut handler: impl FnMut(&mut Ctx<'_, '_>)) -> std::io::Result<Vec<ScriptResult>> {
+    let mut results = Vec::new();
+    for (i, line) in lines.into_iter().enumerate() {
+        let line = line?;
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let (segments, closed) = split_quoted(&line);
+        let outcome = if closed {
+            let (finished, segments, help) = parse_quietly(&segments, Some(&line), &Options::default(), &mut handler);
+            std::io::stdout().write_all(help.as_bytes())?;
+            finished_result(finished, &segments)
+        } else {
+            // A script can't go on to the next line like the loop does, so the quote is a mistake
+            let error = ParseError::new(&segments, segments.len() as u32 - 1, String::from("Unclosed quote"), None);
+            write!(std::io::stdout(), "{}", error)?;
+            Err(error)
+        };
+        let failed = outcome.is_err();
+        results.push(ScriptResult { line_number: i + 1, line, outcome });
+        if failed && stop_on_error {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// How one line given to `run_script` went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptResult {
+    /// The number of the line, starting at 1.
+    pub line_number: usize,
+    pub line: String,
+    pub outcome: Result<(), ParseError>,
+}
+
+/// An error in the input, given back by `try_parse`. Displaying it gives the same text that
+/// `parse` prints for it.
+///
+/// ```
+/// let error = conso::try_parse(&["gö", "1", "2"], |ctx| {
+///     ctx.command("gö")
+///         .arg::<u32>()
+///         .run(|_| {});
+/// }).unwrap().unwrap_err();
+/// assert!(error.to_string().starts_with("# Error\ngö 1 2\n     ^ Excess arguments passed\n"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The input, with any global flags taken out.
+    pub segments: Vec<String>,
+    /// The index of the segment at fault, which is past the end if something was missing.
+    pub depth: u32,
+    pub message: String,
+    /// The help of the command the error is in, if there is any.
+    pub usage: Option<String>,
+}
+
+impl ParseError {
+    fn new(segments: &[&str], depth: u32, message: String, usage: Option<String>) -> Self {
+        Self {
+            segments: segments.iter().map(|&segment| String::from(segment)).collect(),
+            depth,
+            message,
+            usage,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# Error")?;
+        writeln!(f, "{}", self.segments.join(" "))?;
+
+        // Count characters rather than bytes, so the caret lines up with non-ascii segments too
+        let length = self.segments.iter().take(self.depth as usize).map(|segment| segment.chars().count() + 1).sum::<usize>();
+        let width = self.segments.get(self.depth as usize).map(|v| v.chars().count()).unwrap_or(1);
+        writeln!(f, "{}{} {}", " ".repeat(length), "^".repeat(width), self.message)?;
+
+        if let Some(usage) = &self.usage {
+            write!(f, "\nUsage: \n")?;
+            write!(f, "{}", usage)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses and writes any errors or help to `out`, returning how the parsing finished.
+fn parse_with_state(segments: &[&str], line: Option<&str>, options: &Options, out: &mut impl Write, handler: impl FnMut(&mut Ctx<'_, '_>)) -> std::io::Result<FinishedState> {
+    let (finished, segments, mut output) = parse_quietly(segments, line, options, handler);
+    if let FinishedState::Error { depth, message, help } = &finished {
+        output.push_str(&ParseError::new(&segments, *depth, message.clone(), help.clone()).to_string());
+    }
+    // The pager is only given the help, the output of the program goes to the terminal as usual
+    if options.pager && finished == FinishedState::Help && std::io::stdout().is_terminal() && page(&output) {
+        return Ok(finished);
+    }
+    // Written all at once, so that it's fast and doesn't get mixed up with output from other threads
+    out.write_all(output.as_bytes())?;
+    Ok(finished)
+}
+
+/// Shows `text` in `$PAGER`, or `less` if it isn't set, and waits for the user to close it.
+/// Returns false if the pager couldn't be started.
+fn page(text: &str) -> bool {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| String::from("less"));
+    let mut parts = pager.split_whitespace();
+    let Some(program) = parts.next() else {
+        return false;
+    };
+    let Ok(mut child) = std::process::Command::new(program)
+        .args(parts)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+    else {
+        return false;
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading everything, which isn't an error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let _ = child.wait();
+    true
+}
+
+/// Parses the input, returning how the parsing finished, the segments an error should be shown
+/// with, and the help that should be shown.
+fn parse_quietly<'s>(segments: &[&'s str], line: Option<&str>, options: &Options, mut handler: impl FnMut(&mut Ctx<'_, '_>)) -> (FinishedState, Vec<&'s str>, String) {
+    // Collecting the global flags runs the handler, so it's only done if there could be any
+    let mut global_flags = Vec::new();
+    if segments.iter().any(|segment| flag_name(segment).is_some()) {
+        handler(&mut Ctx(CtxInner::CollectGlobalFlags { flags: &mut global_flags }));
+    }
+    let (flags, segments): (Vec<&'s str>, Vec<&'s str>) = segments.iter().partition(|segment| {
+        flag_name(segment).is_some_and(|name| global_flags.iter().any(|flag| flag == name))
+    });
+
+    // `--help` works too, since many users try that first
+    let is_help = |segment: &str| {
+        options.help_keywords.iter().any(|&keyword| segment == keyword || flag_name(segment) == Some(keyword))
+    };
+
+    match &segments[..] {
+        [keyword] if is_help(keyword) => (FinishedState::Help, Vec::new(), render_help(handler)),
+        [keyword, segments @ ..] if is_help(keyword) => {
+            let (help, finished) = build_sub_help(segments, &flags, handler);
+            (finished.unwrap_or(FinishedState::Help), segments.to_vec(), help)
         }
         segments => {
-            let mut input = Segments {
-                original: &segments,
-                iter: segments.iter(),
-                depth: 0,
-            };
+            let mut input = Segments::new(segments, &flags, line);
             let mut finished = None;
-            pick_sub_command(&mut input, &mut finished, handler, true);
-            if let Some(finished) = finished {
-                print_finished_state(&segments, finished);
+            // With no input at all, the help is shown instead of an error saying nothing matched
+            pick_sub_command(&mut input, &mut finished, &mut handler, !segments.is_empty());
+            match (finished, segments) {
+                // `inv add help` shows the help for `inv add` like `help inv add` does, but only if
+                // the input doesn't work as it is, so that something like `say help` still says it
+                (Some(FinishedState::Error { .. }), [segments @ .., keyword]) if is_help(keyword) => {
+                    let (help, finished) = build_sub_help(segments, &flags, handler);
+                    (finished.unwrap_or(FinishedState::Help), segments.to_vec(), help)
+                }
+                (Some(finished), _) => (finished, segments.to_vec(), String::new()),
+                (None, _) => (FinishedState::Help, Vec::new(), render_help(handler)),
+            }
+        }
+    }
+}
+
+/// Renders the help information of all the commands into a string, exactly like the `help`
+/// command would print it.
+pub fn render_help(mut handler: impl FnMut(&mut Ctx<'_, '_>)) -> String {
+    let mut help = HelpFmt::default();
+    let mut ctx = Ctx(CtxInner::BuildHelpInfo {
+        help: &mut help,
+    });
+    handler(&mut ctx);
+    help.line_break();
+    help.output
+}
+
+/// Renders the help information of the command picked by `segments` into a string, like
+/// `help inv add` would print it. If `segments` don't lead to a command, the result is empty.
+///
+/// ```
+/// let help = conso::render_sub_help(&["inv"], |ctx| {
+///     ctx.command("inv").sub_commands(|ctx| {
+///         ctx.command("list").run(|| {});
+///     });
+/// });
+/// assert!(help.contains("list"));
+/// ```
+///
+/// The help of a command starts with a usage line showing its arguments.
+///
+/// ```
+/// let help = conso::render_sub_help(&["inv", "add"], |ctx| {
+///     ctx.command("inv").sub_commands(|ctx| {
+///         ctx.command("add")
+///             .description("Adds an item")
+///             .arg_named::<String>("item")
+///             .arg::<u32>()
+///             .run(|_| {});
+///     });
+/// });
+/// assert!(help.starts_with("add <string> <unsigned integer>\n |  Adds an item\n"));
+/// ```
+pub fn render_sub_help(segments: &[&str], handler: impl FnMut(&mut Ctx<'_, '_>)) -> String {
+    build_sub_help(segments, &[], handler).0
+}
+
+/// Renders the help information of the command picked by `segments`, also giving back whether
+/// that went okay.
+fn build_sub_help(segments: &[&str], flags: &[&str], mut handler: impl FnMut(&mut Ctx<'_, '_>)) -> (String, Option<FinishedState>) {
+    let mut help = HelpFmt::default();
+    let mut finished = None;
+    Command::<()>(DataCommand(CommandInner::BuildSubHelpInfo {
+        input: Segments::new(segments, flags, None),
+        help: &mut help,
+        finished: &mut finished,
+    })).sub_commands(&mut handler);
+    // A command that doesn't exist, or is hidden, gets the same usage as in any other error
+    add_error_usage(&Segments::new(segments, flags, None), &mut finished, handler);
+    help.line_break();
+    (help.output, finished)
+}
+
+/// Walks through all the commands of `handler`, collecting their names, descriptions and
+/// arguments into a tree. Useful for tools that generate documentation or shell completions,
+/// and with the `serde` feature enabled the tree can be serialized, e.g. with `to_json`.
+/// Hidden commands are left out.
+///
+/// ```
+/// let schema = conso::dump_schema(|ctx| {
+///     ctx.command("greet")
+///         .description("Greets someone")
+///         .arg_named::<String>("name")
+///         .run(|name| println!("Hello {}!", name));
+/// });
+/// assert_eq!(schema.sub_commands[0].name, "greet");
+/// assert_eq!(schema.sub_commands[0].args[0].help, "<string>");
+/// ```
+pub fn dump_schema(mut handler: impl FnMut(&mut Ctx<'_, '_>)) -> CommandSchema {
+    let mut schema = CommandSchema::default();
+    handler(&mut Ctx(CtxInner::BuildSchema { schema: &mut schema }));
+    schema
+}
+
+/// A command and everything below it, created by `dump_schema`. The root of the tree has an
+/// empty name, and only contains the top level commands.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandSchema {
+    /// The name of the command as it's shown in the help, e.g. `inv` or `[north|south]`.
+    pub name: String,
+    /// The literal words that match the command, e.g. `north` and `south`. Empty if the command
+    /// doesn't match a fixed set of words.
+    pub literals: Vec<String>,
+    pub description: Option<String>,
+    pub examples: Vec<String>,
+    pub args: Vec<ArgSchema>,
+    /// The options, like `--name value`, where the name is given without the leading `--`.
+    pub options: Vec<ArgSchema>,
+    /// The names of the global flags declared here, without the leading `--`.
+    pub global_flags: Vec<String>,
+    /// The note given to `deprecated`, if the command is deprecated.
+    pub deprecated: Option<String>,
+    pub sub_commands: Vec<CommandSchema>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArgSchema {
+    pub name: Option<String>,
+    /// The help of the argument as it's shown on one line, e.g. `<string>`.
+    pub help: String,
+}
+
+impl CommandSchema {
+    /// The command being built in `CommandInner::BuildSchema`.
+    fn last_command(&mut self) -> &mut CommandSchema {
+        self.sub_commands.last_mut().expect("The command is added when it's created")
+    }
+
+    /// Serializes the tree as pretty printed JSON.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("The schema only contains strings, so it can always be serialized")
+    }
+}
+
+/// A shell to generate completions for with `generate_completions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Generates a script that completes the command names of `handler` in `shell`, for the program
+/// called `bin_name`. Only the names of commands are completed, not argument values, and command
+/// names containing characters that would have to be quoted are left out.
+///
+/// ```
+/// let script = conso::generate_completions(conso::Shell::Bash, "game", |ctx| {
+///     ctx.command("inv").sub_commands(|ctx| {
+///         ctx.command("list").run(|| {});
+///     });
+/// });
+/// assert!(script.contains("complete -F _game game"));
+/// ```
+pub fn generate_completions(shell: Shell, bin_name: &str, handler: impl FnMut(&mut Ctx<'_, '_>)) -> String {
+    generate_completions_with(shell, bin_name, &Options::default(), handler)
+}
+
+/// Like `generate_completions`, but completes the help keywords of `options` instead of `help`.
+///
+/// ```
+/// let options = conso::Options::default().help_keywords(&["hilfe"]);
+/// let script = conso::generate_completions_with(conso::Shell::Bash, "spiel", &options, |ctx| {
+///     ctx.command("inv").run(|| {});
+/// });
+/// assert!(script.contains("compgen -W \"hilfe inv\""));
+/// assert!(!script.contains("help"));
+/// ```
+pub fn generate_completions_with(shell: Shell, bin_name: &str, options: &Options, handler: impl FnMut(&mut Ctx<'_, '_>)) -> String {
+    let schema = dump_schema(handler);
+    let flags = schema.global_flags.iter().map(|flag| format!("--{}", flag)).collect::<Vec<_>>();
+
+    let mut levels = Vec::new();
+    collect_completions(&schema, String::new(), &mut levels);
+    for (_, words) in &mut levels {
+        words.extend(flags.iter().cloned());
+    }
+    levels[0].1.splice(0..0, options.help_keywords.iter().map(|&keyword| String::from(keyword)));
+
+    let function = format!("_{}", bin_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    let mut script = String::new();
+    match shell {
+        Shell::Bash => {
+            script.push_str(&format!("{}() {{\n", function));
+            script.push_str("    local cur prefix i\n");
+            script.push_str("    cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+            script.push_str("    prefix=\"\"\n");
+            script.push_str("    for ((i = 1; i < COMP_CWORD; i++)); do\n");
+            script.push_str("        case \"${COMP_WORDS[i]}\" in\n");
+            script.push_str("            --*) ;;\n");
+            script.push_str("            *) prefix=\"${prefix:+$prefix }${COMP_WORDS[i]}\" ;;\n");
+            script.push_str("        esac\n");
+            script.push_str("    done\n");
+            script.push_str("    case \"$prefix\" in\n");
+            for (path, words) in &levels {
+                script.push_str(&format!("        \"{}\") COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;\n", path, words.join(" ")));
+            }
+            script.push_str("    esac\n");
+            script.push_str("}\n");
+            script.push_str(&format!("complete -F {} {}\n", function, bin_name));
+        }
+        Shell::Zsh => {
+            script.push_str(&format!("#compdef {}\n", bin_name));
+            script.push_str(&format!("{}() {{\n", function));
+            script.push_str("    local prefix=\"${(j: :)${words[2,CURRENT-1]:#--*}}\"\n");
+            script.push_str("    case \"$prefix\" in\n");
+            for (path, words) in &levels {
+                script.push_str(&format!("        \"{}\") compadd -- {} ;;\n", path, words.join(" ")));
+            }
+            script.push_str("    esac\n");
+            script.push_str("}\n");
+            script.push_str(&format!("compdef {} {}\n", function, bin_name));
+        }
+        Shell::Fish => {
+            script.push_str(&format!("function {}_at\n", function));
+            script.push_str("    set -l words (commandline -opc)\n");
+            script.push_str("    set -e words[1]\n");
+            script.push_str("    test \"$argv\" = \"$(string join ' ' -- (string match -v -- '--*' $words))\"\n");
+            script.push_str("end\n");
+            script.push_str(&format!("complete -c {} -f\n", bin_name));
+            for (path, words) in &levels {
+                script.push_str(&format!("complete -c {} -n '{}_at \"{}\"' -a '{}'\n", bin_name, function, path, words.join(" ")));
+            }
+        }
+    }
+
+    script
+}
+
+/// Collects the command names that can follow `path`, and then does the same for every command
+/// below it. Names that would need quoting in a shell are skipped.
+fn collect_completions(schema: &CommandSchema, path: String, levels: &mut Vec<(String, Vec<String>)>) {
+    let is_plain = |word: &String| !word.is_empty() && word.chars().all(|c| c.is_alphanumeric() || "-_.:/+=@,".contains(c));
+
+    let index = levels.len();
+    levels.push((path.clone(), Vec::new()));
+    for command in &schema.sub_commands {
+        for literal in command.literals.iter().filter(|v| is_plain(v)) {
+            levels[index].1.push(literal.clone());
+            if !command.sub_commands.is_empty() {
+                let path = if path.is_empty() { literal.clone() } else { format!("{} {}", path, literal) };
+                collect_completions(command, path, levels);
             }
         }
     }
 }
 
 /// Queries for the user for input in a loop, until a command the user runs
-/// asks the loop to quit.
-pub fn user_loop<T>(mut handler: impl FnMut(&mut Ctx<'_, '_>, &mut ControlFlow<'_, T>)) -> T {
+/// asks the loop to quit, or the input ends.
+///
+/// ```no_run
+/// let exit = conso::user_loop(|ctx, control_flow| {
+///     ctx.command("quit")
+///         .arg::<i32>()
+///         .run(|&code| control_flow.quit(code));
+/// });
+/// std::process::exit(match exit {
+///     conso::LoopExit::Quit(code) => code,
+///     conso::LoopExit::Eof => 0,
+///     conso::LoopExit::Interrupted => 130,
+///     conso::LoopExit::Stopped | conso::LoopExit::Error(_) => 1,
+/// });
+/// ```
+pub fn user_loop<T>(handler: impl FnMut(&mut Ctx<'_, '_>, &mut ControlFlow<'_, T>)) -> LoopExit<T> {
+    user_loop_with(&Options::default(), handler)
+}
+
+/// Like `user_loop`, but with `options` changing how the input is read and parsed.
+///
+/// Segments can be quoted with `"` or `'` to include spaces, and if a quote isn't closed
+/// at the end of a line, the next line continues it, shown with `continuation_prompt`. This
+/// goes for all the loops, not just this one.
+///
+/// ```
+/// let mut note = None;
+/// let input = "note 'a b\nc' \"\"\nquit\n";
+/// let mut output = Vec::new();
+/// conso::user_loop_io(input.as_bytes(), &mut output, |ctx, control_flow| {
+///     ctx.command("note")
+///         .arg::<(String, String)>()
+///         .run(|parts| note = Some(parts.clone()));
+///     ctx.command("quit")
+///         .run(|| control_flow.quit(()));
+/// });
+/// assert_eq!(note, Some((String::from("a b\nc"), String::new())));
+/// assert_eq!(String::from_utf8(output).unwrap(), "~> > ~> ");
+/// ```
+///
+/// ```no_run
+/// let options = conso::Options::default().continuation_prompt("... ");
+/// conso::user_loop_with(&options, |ctx, control_flow| {
+///     ctx.command("note")
+///         .arg::<String>()
+///         .run(|note| println!("Noted: {}", note));
+///
+///     ctx.command("quit")
+///         .run(|| control_flow.quit(()));
+/// });
+/// ```
+pub fn user_loop_with<T>(options: &Options, mut handler: impl FnMut(&mut Ctx<'_, '_>, &mut ControlFlow<'_, T>)) -> LoopExit<T> {
+    let mut input = String::new();
+    loop {
+        if let Some(exit) = loop_exit(read_command(&mut input, options, append_line)) {
+            break exit;
+        }
+        if let Some(exit) = parse_user_line(&input, options, &mut std::io::stdout(), &mut handler, None) {
+            break exit;
+        }
+    }
+}
+
+/// Why a loop like `user_loop` stopped.
+#[derive(Debug)]
+pub enum LoopExit<T> {
+    /// A command called `ControlFlow::quit` with this value.
+    Quit(T),
+    /// There was no more input, like when the user pressed Ctrl-D.
+    Eof,
+    /// Reading the input was interrupted, like when the user pressed Ctrl-C.
+    Interrupted,
+    /// The condition given to `user_loop_while` was false.
+    Stopped,
+    /// Reading the input or writing the output failed.
+    Error(std::io::Error),
+}
+
+/// How a loop should exit after trying to read a line, if it should.
+fn loop_exit<T>(read: std::io::Result<usize>) -> Option<LoopExit<T>> {
+    match read {
+        Ok(0) => Some(LoopExit::Eof),
+        Ok(_) => None,
+        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => Some(LoopExit::Interrupted),
+        Err(err) => Some(LoopExit::Error(err)),
+    }
+}
+
+/// Like `user_loop`, but reads the input from `reader`, and writes the prompt, the help and any
+/// errors to `writer`, which makes it possible to run the loop over something other than the
+/// terminal, or to test it. Output from the commands themselves still goes wherever they write it.
+///
+/// ```
+/// let input = "greet\nquit\n";
+/// let mut output = Vec::new();
+/// let mut greeted = 0;
+/// let result = conso::user_loop_io(input.as_bytes(), &mut output, |ctx, control_flow| {
+///     ctx.command("greet")
+///         .run(|| greeted += 1);
+///
+///     ctx.command("quit")
+///         .run(|| control_flow.quit("bye"));
+/// });
+/// assert!(matches!(result, conso::LoopExit::Quit("bye")));
+/// assert_eq!(greeted, 1);
+/// assert_eq!(String::from_utf8(output).unwrap(), "~> ~> ");
+/// ```
+///
+/// If reading or writing fails, the loop stops with the error.
+///
+/// ```
+/// // Closes after the prompt, so the error about `grete` can't be written
+/// struct Closed;
+///
+/// impl std::io::Write for Closed {
+///     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+///         match buf {
+///             b"~> " => Ok(buf.len()),
+///             _ => Err(std::io::ErrorKind::BrokenPipe.into()),
+///         }
+///     }
+///
+///     fn flush(&mut self) -> std::io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let exit = conso::user_loop_io("grete\n".as_bytes(), Closed, |ctx, _: &mut conso::ControlFlow<()>| {
+///     ctx.command("greet").run(|| {});
+/// });
+/// assert!(matches!(exit, conso::LoopExit::Error(err) if err.kind() == std::io::ErrorKind::BrokenPipe));
+/// ```
+pub fn user_loop_io<T>(mut reader: impl BufRead, writer: impl Write, mut handler: impl FnMut(&mut Ctx<'_, '_>, &mut ControlFlow<'_, T>)) -> LoopExit<T> {
+    let options = Options::default();
+    let mut input = String::new();
+    // Shared with the questions asked through `ControlFlow`, which are asked while parsing
+    let writer = RefCell::new(writer);
+    let mut read = |prompt: &str, input: &mut String| {
+        let mut writer = writer.borrow_mut();
+        write!(writer, "{}", prompt)?;
+        writer.flush()?;
+        reader.read_line(input)
+    };
+    loop {
+        if let Some(exit) = loop_exit(read_command(&mut input, &options, &mut read)) {
+            break exit;
+        }
+        if let Some(exit) = parse_user_line(&input, &options, &mut SharedWriter(&writer), &mut handler, Some(&mut read)) {
+            break exit;
+        }
+    }
+}
+
+/// Writes to a writer that's borrowed for every write, so that it can be used in more places.
+struct SharedWriter<'a, W>(&'a RefCell<W>);
+
+impl<W: Write> Write for SharedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Asks the user a yes or no question on stdin, like before doing something destructive. Only
+/// `y` or `yes` count as yes, anything else is a no, as is an answer that can't be read. Inside a
+/// loop, `ControlFlow::confirm` asks through the input and output of the loop instead.
+///
+/// ```no_run
+/// conso::args(|ctx| {
+///     ctx.command("reset")
+///         .run(|| {
+///             if conso::confirm("Delete everything?") {
+///                 println!("Deleted everything");
+///             }
+///         });
+/// });
+/// ```
+pub fn confirm(prompt: &str) -> bool {
+    let mut input = String::new();
+    read_line(&format!("{} [y/N] ", prompt), &mut input).is_ok() && is_yes(&input)
+}
+
+/// Whether `answer` to a yes or no question is a yes.
+fn is_yes(answer: &str) -> bool {
+    matches!(&*answer.trim().to_lowercase(), "y" | "yes")
+}
+
+/// Like `confirm`, but an empty answer gives back `default`, which is shown capitalized like
+/// `[Y/n]`. Only `y`, `yes`, `n` and `no` are accepted, anything else asks again.
+///
+/// ```no_run
+/// if conso::confirm_default("Keep your save file?", true) {
+///     println!("Kept the save file");
+/// }
+/// ```
+pub fn confirm_default(prompt: &str, default: bool) -> bool {
+    let choices = if default { "[Y/n]" } else { "[y/N]" };
     let mut input = String::new();
     loop {
+        // An answer that can't be read is taken as the default, rather than asking forever
+        if !matches!(read_line(&format!("{} {} ", prompt, choices), &mut input), Ok(1..)) {
+            return default;
+        }
+        match &*input.trim().to_lowercase() {
+            "" => return default,
+            "y" | "yes" => return true,
+            "n" | "no" => return false,
+            _ => println!("Please answer yes or no"),
+        }
+    }
+}
+
+/// Shows `prompt` and reads a line of input from the user without showing what's typed, for
+/// passwords and other secrets. The line break at the end isn't included. Typed characters are
+/// shown again before this returns, even if reading failed.
+///
+/// ```no_run
+/// conso::args(|ctx| {
+///     ctx.command("login")
+///         .arg::<String>()
+///         .run(|user| match conso::read_password("Password: ") {
+///             Ok(password) => println!("Logging in as {} with a password of {} characters", user, password.len()),
+///             Err(err) => println!("Couldn't read the password: {}", err),
+///         });
+/// });
+/// ```
+pub fn read_password(prompt: &str) -> std::io::Result<String> {
+    let mut input = String::new();
+    let read = {
+        let _hidden = HiddenInput::new();
+        read_line(prompt, &mut input)
+    };
+    read?;
+    // The line break typed by the user wasn't shown either
+    writeln!(std::io::stdout())?;
+    input.truncate(input.trim_end_matches(['\r', '\n']).len());
+    Ok(input)
+}
+
+/// Turns off the echoing of typed characters in the terminal until it's dropped. Does nothing if
+/// the input isn't a terminal.
+struct HiddenInput {
+    #[cfg(windows)]
+    mode: Option<u32>,
+}
+
+#[cfg(unix)]
+impl HiddenInput {
+    fn new() -> Self {
+        Self::stty("-echo");
+        Self {}
+    }
+
+    fn stty(setting: &str) {
+        // `stty` changes the terminal it gets as its input, which is the same as ours
+        let _ = std::process::Command::new("stty")
+            .arg(setting)
+            .stdin(std::process::Stdio::inherit())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+}
+
+#[cfg(unix)]
+impl Drop for HiddenInput {
+    fn drop(&mut self) {
+        Self::stty("echo");
+    }
+}
+
+#[cfg(windows)]
+mod console {
+    pub const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    pub const ENABLE_ECHO_INPUT: u32 = 0x4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetStdHandle(handle: u32) -> *mut std::ffi::c_void;
+        pub fn GetConsoleMode(console: *mut std::ffi::c_void, mode: *mut u32) -> i32;
+        pub fn SetConsoleMode(console: *mut std::ffi::c_void, mode: u32) -> i32;
+    }
+}
+
+#[cfg(windows)]
+impl HiddenInput {
+    fn new() -> Self {
+        let mut mode = 0;
+        // SAFETY: The handle is only passed back to the console functions, which check it
+        unsafe {
+            let handle = console::GetStdHandle(console::STD_INPUT_HANDLE);
+            if console::GetConsoleMode(handle, &mut mode) == 0 {
+                return Self { mode: None };
+            }
+            console::SetConsoleMode(handle, mode & !console::ENABLE_ECHO_INPUT);
+        }
+        Self { mode: Some(mode) }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for HiddenInput {
+    fn drop(&mut self) {
+        if let Some(mode) = self.mode {
+            // SAFETY: See `HiddenInput::new`
+            unsafe {
+                console::SetConsoleMode(console::GetStdHandle(console::STD_INPUT_HANDLE), mode);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+impl HiddenInput {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Shows `prompt` and reads a line of input from the user into `input`, replacing what was
+/// there before. Gives back the number of bytes read, which is 0 at the end of the input.
+fn read_line(prompt: &str, input: &mut String) -> std::io::Result<usize> {
+    input.clear();
+    append_line(prompt, input)
+}
+
+/// Like `read_line`, but adds the line to the end of `input`.
+fn append_line(prompt: &str, input: &mut String) -> std::io::Result<usize> {
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "{}", prompt)?;
+    stdout.flush()?;
+    std::io::stdin().read_line(input)
+}
+
+/// Reads a command into `input` with `read`, which adds a line to the end of it after showing a
+/// prompt. While a quote is left open, more lines are read. Gives back the number of bytes read,
+/// which is 0 at the end of the input.
+fn read_command(input: &mut String, options: &Options, mut read: impl FnMut(&str, &mut String) -> std::io::Result<usize>) -> std::io::Result<usize> {
+    'command: loop {
         input.clear();
-        print!("~> ");
-        std::io::stdout().lock().flush().unwrap();
-        std::io::stdin().read_line(&mut input).unwrap();
-        let segments = input.split_whitespace().collect::<Vec<_>>();
-        let mut result = None;
-        parse(&segments, |ctx| handler(ctx, &mut ControlFlow { result: Some(&mut result) }));
-        if let Some(result) = result {
-            break result;
+        let mut total = read("~> ", input)?;
+        while total > 0 && !split_quoted(input).1 {
+            match read(options.continuation_prompt, input) {
+                // At the end of the input, the quote is closed by the end of it
+                Ok(0) => break,
+                Ok(read) => total += read,
+                // Like in a shell, Ctrl-C only throws away the command being typed
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue 'command,
+                Err(err) => return Err(err),
+            }
         }
+        return Ok(total);
     }
 }
 
-fn print_finished_state(segments: &[&str], finished_state: FinishedState) {
-    match finished_state {
-        FinishedState::Okay => {}
-        FinishedState::Help => {},
-        FinishedState::Error { depth, message, help } => {
-            println!("# Error");
-            for (i, segment) in segments.iter().enumerate() {
-                if i > 0 {
-                    print!(" ");
+/// Splits a line typed by the user into segments on whitespace, where a segment starting with
+/// `"` or `'` goes on until the same quote, spaces and line breaks included. The segments point
+/// into `line`, without the quotes. Also gives back whether every quote was closed.
+fn split_quoted(line: &str) -> (Vec<&str>, bool) {
+    let mut segments = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some(first) = rest.chars().next() {
+        let (segment, after) = match first {
+            '"' | '\'' => match rest[1..].find(first) {
+                Some(end) => (&rest[1..end + 1], &rest[end + 2..]),
+                None => {
+                    segments.push(rest[1..].trim_end_matches(['\r', '\n']));
+                    return (segments, false);
                 }
-                print!("{}", segment);
+            },
+            _ => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
             }
-            println!();
+        };
+        segments.push(segment);
+        rest = after.trim_start();
+    }
+    (segments, true)
+}
+
+/// Like `user_loop`, but passes `state` to the handler explicitly on every iteration instead of
+/// it having to be captured. This lets commands be split up into free functions taking `&mut S`.
+pub fn user_loop_stateful<S, T>(state: &mut S, mut handler: impl FnMut(&mut S, &mut Ctx<'_, '_>, &mut ControlFlow<'_, T>)) -> LoopExit<T> {
+    user_loop(|ctx, control_flow| handler(state, ctx, control_flow))
+}
+
+/// Like `user_loop_stateful`, but `condition` is checked before every line is read, and the loop
+/// stops with `LoopExit::Stopped` once it's false. Useful when the loop should end because of
+/// the state, like the game being over, rather than because of a specific command. Commands can
+/// still `quit`.
+///
+/// ```no_run
+/// struct Game {
+///     health: u32,
+/// }
+///
+/// let mut game = Game { health: 3 };
+/// conso::user_loop_while(&mut game, |game| game.health > 0, |game, ctx, _: &mut conso::ControlFlow<()>| {
+///     ctx.command("fight")
+///         .run(|| {
+///             game.health -= 1;
+///             println!("Ouch! {} health left", game.health);
+///         });
+/// });
+/// println!("Game over");
+/// ```
+pub fn user_loop_while<S, T>(state: &mut S, mut condition: impl FnMut(&S) -> bool, mut handler: impl FnMut(&mut S, &mut Ctx<'_, '_>, &mut ControlFlow<'_, T>)) -> LoopExit<T> {
+    let mut input = String::new();
+    let options = Options::default();
+    while condition(state) {
+        if let Some(exit) = loop_exit(read_command(&mut input, &options, append_line)) {
+            return exit;
+        }
+        if let Some(exit) = parse_user_line(&input, &options, &mut std::io::stdout(), &mut |ctx, control_flow| handler(state, ctx, control_flow), None) {
+            return exit;
+        }
+    }
+    LoopExit::Stopped
+}
+
+/// Like `user_loop_stateful`, but split into two halves so that commands can borrow from the
+/// state while they're being picked. `handler` only gets `&S`, and the commands in it return an
+/// action instead of changing the state themselves. The action of the command that ran is then
+/// passed to `apply` together with `&mut S`. Since only `apply` changes the state, the parsing
+/// code can freely borrow it, and can be split into methods taking `&self`. See
+/// `user_loop_actions_io` for reading the input from somewhere else.
+///
+/// ```no_run
+/// struct Game {
+///     inventory: Vec<String>,
+///     gold: u32,
+/// }
+///
+/// enum Action {
+///     Sell(usize),
+///     Quit,
+/// }
+///
+/// let mut game = Game { inventory: vec!["sword".to_string()], gold: 0 };
+/// conso::user_loop_actions(&mut game, |game, ctx| {
+///     ctx.command("sell")
+///         .constrained_arg(conso::choices(game.inventory.iter().cloned().zip(0..)))
+///         .run(|&i| Action::Sell(i));
+///     ctx.command("quit").run(|| Action::Quit);
+/// }, |game, action, control_flow| match action {
+///     Action::Sell(i) => {
+///         game.inventory.remove(i);
+///         game.gold += 10;
+///     }
+///     Action::Quit => control_flow.quit(()),
+/// });
+/// ```
+pub fn user_loop_actions<S, A, T>(
+    state: &mut S,
+    mut handler: impl FnMut(&S, &mut Ctx<'_, '_, A>),
+    mut apply: impl FnMut(&mut S, A, &mut ControlFlow<'_, T>),
+) -> LoopExit<T> {
+    user_loop(|ctx, control_flow| {
+        let mut action = None;
+        ctx.scope(|picked| action = Some(picked), |ctx| handler(state, ctx));
+        if let Some(action) = action {
+            apply(state, action, control_flow);
+        }
+    })
+}
+
+/// Like `user_loop_actions`, but reads from `reader` and writes to `writer` like `user_loop_io`.
+///
+/// ```
+/// struct Game {
+///     inventory: Vec<String>,
+///     gold: u32,
+/// }
+///
+/// enum Action {
+///     Sell(usize),
+///     Quit,
+/// }
+///
+/// let mut game = Game { inventory: vec!["sword".to_string(), "shield".to_string()], gold: 0 };
+/// let mut output = Vec::new();
+/// let exit = conso::user_loop_actions_io("sell shield\nsell shield\nquit\n".as_bytes(), &mut output, &mut game, |game, ctx| {
+///     ctx.command("sell")
+///         .constrained_arg(conso::choices(game.inventory.iter().cloned().zip(0..)))
+///         .run(|&i| Action::Sell(i));
+///     ctx.command("quit").run(|| Action::Quit);
+/// }, |game, action, control_flow| match action {
+///     Action::Sell(i) => {
+///         game.inventory.remove(i);
+///         game.gold += 10;
+///     }
+///     Action::Quit => control_flow.quit("bye"),
+/// });
+/// assert!(matches!(exit, conso::LoopExit::Quit("bye")));
+/// assert_eq!(game.inventory, ["sword"]);
+/// assert_eq!(game.gold, 10);
+///
+/// // The shield was already sold the second time
+/// let output = String::from_utf8(output).unwrap();
+/// assert!(output.contains("\nsell shield\n     ^^^^^^ Invalid argument, expected [sword]\n"));
+/// ```
+pub fn user_loop_actions_io<S, A, T>(
+    reader: impl BufRead,
+    writer: impl Write,
+    state: &mut S,
+    mut handler: impl FnMut(&S, &mut Ctx<'_, '_, A>),
+    mut apply: impl FnMut(&mut S, A, &mut ControlFlow<'_, T>),
+) -> LoopExit<T> {
+    user_loop_io(reader, writer, |ctx, control_flow| {
+        let mut action = None;
+        ctx.scope(|picked| action = Some(picked), |ctx| handler(state, ctx));
+        if let Some(action) = action {
+            apply(state, action, control_flow);
+        }
+    })
+}
 
-            let length = segments.iter().take(depth as usize).map(|segment| segment.len() + 1).sum::<usize>();
-            println!("{}{} {}", " ".repeat(length), "^".repeat(segments.get(depth as usize).map(|v| v.len()).unwrap_or(1)), message);
+/// The maximum number of lines kept in the history file of `user_loop_with_history`.
+#[cfg(feature = "history")]
+const HISTORY_SIZE: usize = 1000;
 
-            if let Some(help) = help {
-                print!("\nUsage: \n");
-                print!("{}", help);
+/// Like `user_loop`, but reads input through a line editor, so previous commands can be
+/// recalled with the arrow keys. The history is loaded from and saved to `path`, where
+/// a leading `~/` refers to the home directory. Consecutive identical lines are only
+/// stored once, and at most `HISTORY_SIZE` lines are kept.
+#[cfg(feature = "history")]
+pub fn user_loop_with_history<T>(path: impl AsRef<std::path::Path>, mut handler: impl FnMut(&mut Ctx<'_, '_>, &mut ControlFlow<'_, T>)) -> LoopExit<T> {
+    use rustyline::error::ReadlineError;
+
+    let path = expand_home(path.as_ref());
+    let config = rustyline::Config::builder()
+        .max_history_size(HISTORY_SIZE).unwrap()
+        .history_ignore_dups(true).unwrap()
+        .build();
+    let mut editor = rustyline::DefaultEditor::with_config(config).unwrap();
+    // The history file not existing yet is fine, it's created on the first save
+    let _ = editor.load_history(&path);
+
+    let options = Options::default();
+    let mut input = String::new();
+    loop {
+        let read = read_command(&mut input, &options, |prompt, input| match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                Ok(line.len() + 1)
             }
+            Err(ReadlineError::Interrupted) => Err(std::io::ErrorKind::Interrupted.into()),
+            Err(ReadlineError::Eof) => Ok(0),
+            Err(ReadlineError::Io(err)) => Err(err),
+            Err(err) => Err(std::io::Error::other(err)),
+        });
+        if let Some(exit) = loop_exit(read) {
+            break exit;
+        }
+
+        if !input.trim().is_empty() {
+            let _ = editor.add_history_entry(input.trim_end());
+            // Failing to write the history shouldn't stop the user from running commands
+            let _ = editor.save_history(&path);
+        }
+
+        if let Some(exit) = parse_user_line(&input, &options, &mut std::io::stdout(), &mut handler, None) {
+            break exit;
+        }
+    }
+}
+
+#[cfg(feature = "history")]
+fn expand_home(path: &std::path::Path) -> std::path::PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => std::path::Path::new(&home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Parses a single line of user input, returning how the loop should exit if the command asked
+/// it to quit, or if writing the help or errors to `out` failed.
+fn parse_user_line<T>(input: &str, options: &Options, out: &mut impl Write, handler: &mut impl FnMut(&mut Ctx<'_, '_>, &mut ControlFlow<'_, T>), mut ask: Option<&mut Ask<'_>>) -> Option<LoopExit<T>> {
+    let (segments, _) = split_quoted(input);
+    let mut result = None;
+    let reported = parse_and_report(&segments, Some(input), options, out, |ctx| {
+        let mut restart = false;
+        // The cast shortens the lifetime of `ask` to that of the `ControlFlow`
+        handler(ctx, &mut ControlFlow { result: Some(&mut result), restart: Some(&mut restart), ask: ask.as_deref_mut().map(|ask| ask as &mut Ask<'_>) });
+        if restart {
+            ctx.finish();
         }
+    });
+    match (result, reported) {
+        (Some(result), _) => Some(LoopExit::Quit(result)),
+        (None, Err(err)) => Some(LoopExit::Error(err)),
+        (None, Ok(_)) => None,
     }
 }
 
 fn pick_sub_command<'input>(input: &mut Segments<'input>, finished: &mut Option<FinishedState>, mut handler: impl FnMut(&mut Ctx<'_, 'input>), require_finish: bool) {
     let mut output = None;
+    let mut literals = Vec::new();
     let mut ctx = Ctx(CtxInner::PickCommand {
         input: input.clone(),
         output: &mut output,
         finished,
+        literals: &mut literals,
     });
     handler(&mut ctx);
 
@@ -117,13 +1331,16 @@ fn pick_sub_command<'input>(input: &mut Segments<'input>, finished: &mut Option<
         }
     }
 
+    add_error_usage(input, finished, handler);
+}
+
+/// If `finished` is an error without any help, adds the usage of the commands of `handler` that
+/// the error is in, where `input` is what they were picked from.
+fn add_error_usage<'input>(input: &Segments<'input>, finished: &mut Option<FinishedState>, mut handler: impl FnMut(&mut Ctx<'_, 'input>)) {
     // If we have an upstream error without any help, generate the full help
     // information
     if let Some(FinishedState::Error { depth, help: help_opt @ None, .. }) = finished {
-        let mut help = HelpFmt {
-            output: Some(String::new()),
-            ..Default::default()
-        };
+        let mut help = HelpFmt::default();
 
         if *depth == input.depth {
             let mut ctx = Ctx(CtxInner::BuildHelpInfo {
@@ -131,68 +1348,358 @@ fn pick_sub_command<'input>(input: &mut Segments<'input>, finished: &mut Option<
             });
             handler(&mut ctx);
         } else {
-            for part in &input.original[.. *depth as usize] {
-                help.push_word(part);
-            }
-            help.indent();
-
-            let mut sub_finished = None;
-            let sub_segments = &input.original[input.depth as usize .. *depth as usize];
-            let sub_input = Segments {
-                original: sub_segments,
-                iter: sub_segments.iter(),
-                depth: 0,
-            };
-            let mut ctx = Ctx(CtxInner::BuildSubHelpInfo {
-                input: sub_input,
-                finished: &mut sub_finished,
-                help: &mut help,
-            });
-            handler(&mut ctx);
+            // Arguments of the command at fault would leave its usage out, so find the longest
+            // part of the path that names a command
+            let end = (input.depth as usize + 1 ..= *depth as usize)
+                .rev()
+                .find(|&end| error_usage(input, end, &mut handler).1 == Some(FinishedState::Help))
+                .unwrap_or(*depth as usize);
+            help = error_usage(input, end, &mut handler).0;
         }
 
         help.line_break();
 
-        *help_opt = help.output.take();
+        *help_opt = Some(help.output);
     }
 }
 
-#[derive(Clone)]
-pub struct Segments<'a> {
+/// The usage shown for an error in the sub commands of `input`, for the segments up to `end`,
+/// under a header with the path leading up to them. Also gives back how building it finished,
+/// which is `FinishedState::Help` if the segments end at a command.
+fn error_usage<'input>(input: &Segments<'input>, end: usize, handler: &mut impl FnMut(&mut Ctx<'_, 'input>)) -> (HelpFmt, Option<FinishedState>) {
+    let mut help = HelpFmt::default();
+    // A header with the path makes it clear which command the usage is for
+    if let Some((last, path)) = input.original[.. input.depth as usize].split_last() {
+        for part in path {
+            help.push_word(part);
+        }
+        help.push_word(&format!("{}:", last));
+        help.indent();
+    }
+
+    let mut finished = None;
+    let sub_segments = &input.original[input.depth as usize .. end];
+    let mut ctx = Ctx(CtxInner::BuildSubHelpInfo {
+        input: Segments::new(sub_segments, input.flags, input.line),
+        finished: &mut finished,
+        help: &mut help,
+    });
+    handler(&mut ctx);
+    (help, finished)
+}
+
+#[derive(Clone)]
+pub struct Segments<'a> {
     original: &'a [&'a str],
     iter: Iter<'a, &'a str>,
     depth: u32,
+    /// The global flags that were given, which have been removed from the other segments.
+    flags: &'a [&'a str],
+    /// The segments that were consumed by commands (but not their arguments) so far.
+    path: Vec<&'a str>,
+    /// The indices of segments after `depth` that were already consumed out of order, by options.
+    taken: Vec<u32>,
+    /// The index of the segment the current command was matched at.
+    command_depth: u32,
+    /// How many arguments of the current command have been parsed so far.
+    arg_index: u32,
+    /// The index of the segment that made parsing fail, if a parser knew which one it was.
+    failed_at: Option<u32>,
+    /// What was expected at `failed_at`, if the parser that failed said so.
+    expected: Option<String>,
+    /// The line of input the segments were split from, if they were typed in as a single line.
+    line: Option<&'a str>,
+    /// The note given to `deprecated` by the command being parsed, or one leading up to it.
+    deprecated: Option<&'static str>,
 }
 
 impl<'a> Segments<'a> {
     pub fn finished(&self) -> bool {
-        self.iter.as_slice().is_empty()
+        self.unconsumed().next().is_none()
     }
 
-    pub fn next(&mut self) -> Option<&'a str> {
-        match self.iter.next() {
-            Some(v) => {
-                self.depth += 1;
-                Some(v)
+    /// Returns the next segment without consuming it.
+    pub fn peek(&self) -> Option<&'a str> {
+        self.unconsumed().next().map(|(_, segment)| segment)
+    }
+
+    /// Returns all the segments that haven't been consumed yet.
+    pub fn remaining(&self) -> Vec<&'a str> {
+        self.unconsumed().map(|(_, segment)| segment).collect()
+    }
+
+    /// The segments that haven't been consumed yet, along with their indices.
+    fn unconsumed(&self) -> impl Iterator<Item = (u32, &'a str)> + '_ {
+        (self.depth..).zip(self.iter.as_slice().iter().copied())
+            .filter(|(i, _)| !self.taken.contains(i))
+    }
+
+    /// Segments for parsing `original`, where `flags` are the global flags that were taken out of
+    /// it, and `line` is the line it was split from, if there was one.
+    fn new(original: &'a [&'a str], flags: &'a [&'a str], line: Option<&'a str>) -> Self {
+        Segments {
+            original,
+            iter: original.iter(),
+            depth: 0,
+            flags,
+            path: Vec::new(),
+            taken: Vec::new(),
+            command_depth: 0,
+            arg_index: 0,
+            failed_at: None,
+            expected: None,
+            line,
+            deprecated: None,
+        }
+    }
+
+    /// Segments for parsing `values` on their own, outside of any command.
+    fn detached(values: &'a [&'a str]) -> Self {
+        Self::new(values, &[], None)
+    }
+
+    /// Finds `--name value` or `--name=value` anywhere in the rest of the input, and consumes it
+    /// so that the other arguments don't see it. On errors, gives back the index of the segment
+    /// at fault along with a message.
+    fn take_option<C, O>(&mut self, name: &str, constraint: &C) -> Result<Option<O>, (u32, String)>
+    where
+        C: for<'b> ConstrainedArg<'b, Output = O>,
+    {
+        let flag = format!("--{}", name);
+        let expected = HelpFmt::render_line(|fmt| constraint.help(fmt));
+        let mut unconsumed = self.unconsumed();
+        let Some((index, value)) = unconsumed.find_map(|(i, segment)| {
+            let rest = flag_name(segment)?.strip_prefix(name)?;
+            match rest.strip_prefix('=') {
+                Some(value) => Some((i, Some((i, value)))),
+                None if rest.is_empty() => Some((i, None)),
+                None => None,
             }
-            None => {
-                None
+        }) else {
+            return Ok(None);
+        };
+
+        // `--name value`, where the value is the next segment
+        let Some((value_index, value)) = value.or_else(|| unconsumed.next()) else {
+            return Err((index, format!("Missing value for option {}, expected {}", flag, expected)));
+        };
+        drop(unconsumed);
+
+        let values = [value];
+        let mut value_segments = Segments::detached(&values);
+        match constraint.parse(&mut value_segments).filter(|_| value_segments.finished()) {
+            Some(value) => {
+                self.taken.push(index);
+                self.taken.push(value_index);
+                Ok(Some(value))
+            }
+            None => Err((value_index, format!("Invalid value for option {}, expected {}", flag, expected))),
+        }
+    }
+
+    /// Consumes every `--long`, and every cluster of the `short` flag like `-vvv`, in the rest of
+    /// the input, giving back how many times the flag was given.
+    fn take_flag_count(&mut self, short: char, long: &str) -> u32 {
+        let mut count = 0;
+        let mut taken = Vec::new();
+        for (i, segment) in self.unconsumed() {
+            let cluster = segment.strip_prefix('-').filter(|cluster| {
+                // Like with long flags, a `-` followed by a digit is always a value
+                !cluster.is_empty() && !short.is_ascii_digit() && cluster.chars().all(|c| c == short)
+            });
+            let times = match cluster {
+                Some(cluster) => cluster.chars().count() as u32,
+                None if flag_name(segment) == Some(long) => 1,
+                None => continue,
+            };
+            count += times;
+            taken.push(i);
+        }
+        self.taken.extend(taken);
+        count
+    }
+
+    /// Consumes every `--name` in the rest of the input for the `names`, giving back which one was
+    /// given. On a second, different one, gives back its index along with a message instead.
+    fn take_exclusive_flags(&mut self, names: &'static [&'static str]) -> Result<Option<&'static str>, (u32, String)> {
+        let mut given = None;
+        let mut taken = Vec::new();
+        for (i, segment) in self.unconsumed() {
+            let Some(&name) = names.iter().find(|&&name| flag_name(segment) == Some(name)) else {
+                continue;
+            };
+            if given.is_some_and(|given| given != name) {
+                let flags = names.iter().map(|name| format!("--{}", name)).collect::<Vec<_>>();
+                return Err((i, format!("Only one of {} can be given", flags.join(", "))));
+            }
+            given = Some(name);
+            taken.push(i);
+        }
+        self.taken.extend(taken);
+        Ok(given)
+    }
+
+    /// The part of the original line from the start of `first` to the end of `last`, exactly as
+    /// it was typed. Only available when the input was a single line, like in `user_loop`.
+    fn raw_span(&self, first: &'a str, last: &'a str) -> Option<&'a str> {
+        let line = self.line?;
+        // The segments were split from the line, so they point into it
+        let offset = |segment: &str| {
+            (segment.as_ptr() as usize).checked_sub(line.as_ptr() as usize)
+                .filter(|&offset| offset + segment.len() <= line.len())
+        };
+        let (mut start, mut end) = (offset(first)?, offset(last)? + last.len());
+        // Segments are only next to a quote if they were quoted, and then the quotes are included
+        if start > 0 && line[..start].ends_with(['"', '\'']) {
+            start -= 1;
+        }
+        if line[end..].starts_with(['"', '\'']) {
+            end += 1;
+        }
+        line.get(start..end)
+    }
+
+    /// Saves the current position, so that it can be gone back to with `restore`.
+    /// This is how parsers should backtrack when they fail to parse something.
+    pub fn checkpoint(&self) -> Checkpoint<'a> {
+        Checkpoint {
+            remaining: self.iter.as_slice(),
+            depth: self.depth,
+        }
+    }
+
+    /// The segments consumed in order since `checkpoint` was created.
+    fn consumed_since(&self, checkpoint: Checkpoint<'a>) -> impl Iterator<Item = &'a str> + '_ {
+        (checkpoint.depth..self.depth).zip(checkpoint.remaining.iter().copied())
+            .filter(|(i, _)| !self.taken.contains(i))
+            .map(|(_, segment)| segment)
+    }
+
+    /// The segments that weren't consumed yet when `checkpoint` was created.
+    fn unconsumed_since(&self, checkpoint: Checkpoint<'a>) -> impl Iterator<Item = &'a str> + '_ {
+        (checkpoint.depth..).zip(checkpoint.remaining.iter().copied())
+            .filter(|(i, _)| !self.taken.contains(i))
+            .map(|(_, segment)| segment)
+    }
+
+    /// Warns on stderr that the command about to run is deprecated, if it is.
+    fn warn_deprecated(&self) {
+        if let Some(note) = self.deprecated {
+            eprintln!("Warning: this command is deprecated, {}", note);
+        }
+    }
+
+    /// The finished state for a command that ran with this input.
+    fn okay(&self) -> FinishedState {
+        FinishedState::Okay {
+            path: self.path.iter().map(|v| String::from(*v)).collect(),
+        }
+    }
+
+    pub fn restore(&mut self, checkpoint: Checkpoint<'a>) {
+        self.iter = checkpoint.remaining.iter();
+        self.depth = checkpoint.depth;
+        self.failed_at = None;
+        self.expected = None;
+    }
+
+    /// Marks the segment at `checkpoint` as the one at fault when parsing fails, so errors can
+    /// point at it instead of at the start of the argument.
+    pub fn fail_at(&mut self, checkpoint: Checkpoint<'a>) {
+        self.failed_at = Some(checkpoint.depth);
+        self.expected = None;
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&'a str> {
+        loop {
+            let v = self.iter.next()?;
+            self.depth += 1;
+            if !self.taken.contains(&(self.depth - 1)) {
+                break Some(v);
             }
         }
     }
 }
 
-#[derive(Debug)]
-enum FinishedState {
-    Okay,
+/// A position in some `Segments`, created by `Segments::checkpoint`.
+#[derive(Clone, Copy)]
+pub struct Checkpoint<'a> {
+    remaining: &'a [&'a str],
+    depth: u32,
+}
+
+/// How parsing some input ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinishedState {
+    /// A command ran, where `path` is the segments that were matched by the commands leading up to
+    /// it, not including any arguments.
+    Okay {
+        path: Vec<String>,
+    },
+    /// Help information was shown.
     Help,
     Error {
+        /// The index of the segment the error points at.
         depth: u32,
         message: String,
+        /// The usage information shown below the error.
         help: Option<String>,
     },
 }
 
+/// Helpers for testing command trees without running a program.
+///
+/// ```
+/// use std::num::NonZeroU32;
+///
+/// fn commands(ctx: &mut conso::Ctx) {
+///     ctx.command("gö")
+///         .arg_named::<NonZeroU32>("count")
+///         .run(|_| {});
+/// }
+///
+/// let missing = conso::test::run(&["gö"], commands);
+/// assert!(missing.output.contains("\ngö\n   ^ Missing argument: count, expected <positive integer>\n"));
+///
+/// let invalid = conso::test::run(&["gö", "0"], commands);
+/// assert!(invalid.output.contains("\ngö 0\n   ^ Invalid argument: count, expected <positive integer>\n"));
+///
+/// assert_eq!(conso::test::run(&["gö", "1"], commands).state, conso::FinishedState::Okay { path: vec![String::from("gö")] });
+/// ```
+pub mod test {
+    use super::{parse_with_state, Ctx, FinishedState, Options};
+
+    /// What running some input through a command tree produced, see `run`.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TestOutput {
+        /// The help and errors that would have been printed. Output from the commands
+        /// themselves isn't included.
+        pub output: String,
+        pub state: FinishedState,
+    }
+
+    /// Parses `segments` like `parse` does, but captures what would have been printed instead.
+    ///
+    /// ```
+    /// let result = conso::test::run(&["greet", "bob", "extra"], |ctx| {
+    ///     ctx.command("greet")
+    ///         .arg_named::<String>("name")
+    ///         .run(|_| {});
+    /// });
+    /// assert!(result.output.starts_with("# Error\ngreet bob extra\n          ^^^^^ Excess arguments passed\n"));
+    /// assert!(matches!(result.state, conso::FinishedState::Error { depth: 2, .. }));
+    /// ```
+    pub fn run(segments: &[&str], handler: impl FnMut(&mut Ctx<'_, '_>)) -> TestOutput {
+        let mut output = Vec::new();
+        let state = parse_with_state(segments, None, &Options::default(), &mut output, handler).expect("Writing to a Vec can't fail");
+        TestOutput {
+            output: String::from_utf8(output).expect("Everything written is valid utf8"),
+            state,
+        }
+    }
+}
+
 /// The base struct to build "command trees".
 pub struct Ctx<'r, 'input, Ret = ()>(CtxInner<'r, 'input, Ret>);
 
@@ -201,6 +1708,8 @@ enum CtxInner<'r, 'input, Ret> {
         input: Segments<'input>,
         output: &'r mut Option<Ret>,
         finished: &'r mut Option<FinishedState>,
+        /// The literal command names seen so far, to warn about duplicates in debug builds.
+        literals: &'r mut Vec<String>,
     },
     BuildSubHelpInfo {
         input: Segments<'input>,
@@ -210,6 +1719,14 @@ enum CtxInner<'r, 'input, Ret> {
     BuildHelpInfo {
         help: &'r mut HelpFmt,
     },
+    /// Only collects the names of global flags; every command is skipped.
+    CollectGlobalFlags {
+        flags: &'r mut Vec<String>,
+    },
+    /// Adds every command to `schema`, see `dump_schema`.
+    BuildSchema {
+        schema: &'r mut CommandSchema,
+    },
 }
 
 impl<'input, Ret> Ctx<'_, 'input, Ret> {
@@ -219,8 +1736,8 @@ impl<'input, Ret> Ctx<'_, 'input, Ret> {
         let mut inner_output = None;
 
         match &mut self.0 {
-            CtxInner::PickCommand { input, output, finished } => {
-                let mut ctx = Ctx(CtxInner::PickCommand { input: input.clone(), finished: &mut **finished, output: &mut inner_output });
+            CtxInner::PickCommand { input, output, finished, literals } => {
+                let mut ctx = Ctx(CtxInner::PickCommand { input: input.clone(), finished, output: &mut inner_output, literals });
                 handler(&mut ctx);
 
                 if output.is_none() {
@@ -235,6 +1752,189 @@ impl<'input, Ret> Ctx<'_, 'input, Ret> {
                 let mut ctx = Ctx(CtxInner::BuildHelpInfo { help: &mut **help });
                 handler(&mut ctx);
             }
+            CtxInner::CollectGlobalFlags { flags } => {
+                let mut ctx = Ctx(CtxInner::CollectGlobalFlags { flags });
+                handler(&mut ctx);
+            }
+            CtxInner::BuildSchema { schema } => {
+                let mut ctx = Ctx(CtxInner::BuildSchema { schema });
+                handler(&mut ctx);
+            }
+        }
+    }
+
+    /// Groups the commands added in `handler` under a labeled section in the help information.
+    /// When parsing, the group is completely transparent, so the name is not matched against the input.
+    pub fn group(&mut self, name: &str, handler: impl FnOnce(&mut Ctx<'_, 'input, Ret>)) {
+        match &mut self.0 {
+            CtxInner::PickCommand { input, output, finished, literals } => {
+                let mut ctx = Ctx(CtxInner::PickCommand { input: input.clone(), output, finished, literals });
+                handler(&mut ctx);
+            }
+            CtxInner::BuildSubHelpInfo { input, help, finished } => {
+                let mut ctx = Ctx(CtxInner::BuildSubHelpInfo { input: input.clone(), help, finished });
+                handler(&mut ctx);
+            }
+            CtxInner::BuildHelpInfo { help } => {
+                help.push_word(&format!("{}:", name));
+                help.indent();
+                let mut ctx = Ctx(CtxInner::BuildHelpInfo { help });
+                handler(&mut ctx);
+                help.deindent();
+            }
+            CtxInner::CollectGlobalFlags { flags } => {
+                let mut ctx = Ctx(CtxInner::CollectGlobalFlags { flags });
+                handler(&mut ctx);
+            }
+            CtxInner::BuildSchema { schema } => {
+                let mut ctx = Ctx(CtxInner::BuildSchema { schema });
+                handler(&mut ctx);
+            }
+        }
+    }
+
+    /// Declares a flag like `--verbose`, that can be given anywhere in the input regardless of
+    /// which command runs. It's removed from the input before any commands are matched, so it
+    /// doesn't get in the way of them. Returns whether the flag was given.
+    ///
+    /// Global flags should be declared once, on the top level `Ctx`, and the returned value passed
+    /// on to the commands that need it.
+    ///
+    /// A `-` followed by a digit is never a flag, so negative numbers can still be given as values.
+    /// ```
+    /// let mut moved = None;
+    /// conso::parse(&["move", "-5", "--verbose"], |ctx| {
+    ///     let verbose = ctx.global_flag("verbose");
+    ///     ctx.command("move")
+    ///         .constrained_arg(conso::radix_int::<i32>())
+    ///         .run(|&steps| moved = Some((steps, verbose)));
+    /// });
+    /// assert_eq!(moved, Some((-5, true)));
+    ///
+    /// let mut range = None;
+    /// conso::parse(&["move", "-5..-1"], |ctx| {
+    ///     ctx.command("move")
+    ///         .arg::<std::ops::Range<i32>>()
+    ///         .run(|steps| range = Some(steps.clone()));
+    /// });
+    /// assert_eq!(range, Some(-5..-1));
+    /// ```
+    ///
+    /// Without any flags in the input, the handler still only runs once.
+    /// ```
+    /// let mut runs = 0;
+    /// conso::parse(&["move", "5"], |ctx| {
+    ///     runs += 1;
+    ///     let _verbose = ctx.global_flag("verbose");
+    ///     ctx.command("move")
+    ///         .arg::<i32>()
+    ///         .run(|_| {});
+    /// });
+    /// assert_eq!(runs, 1);
+    /// ```
+    pub fn global_flag(&mut self, name: &str) -> bool {
+        match &mut self.0 {
+            CtxInner::PickCommand { input, .. } | CtxInner::BuildSubHelpInfo { input, .. } => {
+                input.flags.iter().any(|&flag| flag_name(flag) == Some(name))
+            }
+            CtxInner::BuildHelpInfo { help } => {
+                help.push_word(&format!("--{}", name));
+                help.line_break();
+                false
+            }
+            CtxInner::CollectGlobalFlags { flags } => {
+                flags.push(String::from(name));
+                false
+            }
+            CtxInner::BuildSchema { schema } => {
+                schema.global_flags.push(String::from(name));
+                false
+            }
+        }
+    }
+
+    /// Marks the input as handled, so that nothing else runs and no error is emitted.
+    fn finish(&mut self) {
+        if let CtxInner::PickCommand { finished, input, .. } = &mut self.0 {
+            if finished.is_none() {
+                **finished = Some(input.okay());
+            }
+        }
+    }
+
+    /// Hands the input over to `handler` to parse however it likes, for grammars that don't fit
+    /// commands and arguments, like free-form expressions. If `handler` gives back a value, the
+    /// input counts as handled and nothing after this runs. If it gives back `None`, whatever it
+    /// consumed is put back and the input is tried on the next commands.
+    ///
+    /// Since there's nothing to show for it, it's left out of the help.
+    ///
+    /// ```
+    /// let mut sum = None;
+    /// conso::parse(&["1", "+", "2"], |ctx| {
+    ///     ctx.raw(|segments| {
+    ///         let a = segments.next()?.parse::<i32>().ok()?;
+    ///         (segments.next()? == "+").then_some(())?;
+    ///         let b = segments.next()?.parse::<i32>().ok()?;
+    ///         sum = Some(a + b);
+    ///         Some(())
+    ///     });
+    /// });
+    /// assert_eq!(sum, Some(3));
+    ///
+    /// // `quit` isn't a number, so it's put back for the `quit` command
+    /// let mut ran = Vec::new();
+    /// conso::parse(&["quit"], |ctx| {
+    ///     ctx.raw(|segments| {
+    ///         let number = segments.next()?.parse::<i32>().ok()?;
+    ///         ran.push(format!("number {}", number));
+    ///         Some(())
+    ///     });
+    ///     ctx.command("quit").run(|| ran.push(String::from("quit")));
+    /// });
+    /// assert_eq!(ran, ["quit"]);
+    ///
+    /// // When nothing takes it, it's the usual error
+    /// let unknown = conso::test::run(&["x"], |ctx| {
+    ///     ctx.raw(|segments| segments.next()?.parse::<i32>().ok().map(drop));
+    /// });
+    /// assert!(unknown.output.contains("\nx\n^ Input did not match any wanted command\n"));
+    /// assert!(matches!(unknown.state, conso::FinishedState::Error { depth: 0, .. }));
+    /// ```
+    pub fn raw(&mut self, handler: impl FnOnce(&mut Segments<'input>) -> Option<Ret>) {
+        if let CtxInner::PickCommand { finished: finished @ None, input, output, .. } = &mut self.0 {
+            let mut input = input.clone();
+            if let Some(result) = handler(&mut input) {
+                **output = Some(result);
+                **finished = Some(input.okay());
+            }
+        }
+    }
+
+    /// Adds a simple command for every `(name, description, value)` in `table`. When one of them
+    /// runs, `handler` is called with its value. Since there's only one handler, it can borrow
+    /// whatever state it needs, even when the commands are all different; the values can be
+    /// functions taking that state if the commands need to do completely different things.
+    ///
+    /// ```
+    /// let mut position = (0, 0);
+    /// conso::parse(&["w"], |ctx| {
+    ///     ctx.commands([
+    ///         ("w", "Move forward", (0, 1)),
+    ///         ("s", "Move backwards", (0, -1)),
+    ///     ], |(x, y)| {
+    ///         position.0 += x;
+    ///         position.1 += y;
+    ///     });
+    /// });
+    /// assert_eq!(position, (0, 1));
+    /// ```
+    pub fn commands<T>(&mut self, table: impl IntoIterator<Item = (&'static str, &'static str, T)>, handler: impl FnOnce(T) -> Ret) {
+        let mut handler = Some(handler);
+        for (name, description, value) in table {
+            self.command(name)
+                .description(description)
+                .run(|| handler.take().expect("Only one command runs")(value));
         }
     }
 
@@ -242,11 +1942,204 @@ impl<'input, Ret> Ctx<'_, 'input, Ret> {
         self.command(())
     }
 
+    /// Like `otherwise`, but instead of ignoring the input that didn't match any of the previous
+    /// commands, it's parsed as a `T` and passed on.
+    ///
+    /// ```
+    /// fn commands(ctx: &mut conso::Ctx) {
+    ///     ctx.command("quit").run(|| {});
+    ///     ctx.otherwise_arg::<u32>().run(|_| {});
+    /// }
+    ///
+    /// let mut picked = None;
+    /// conso::parse(&["5"], |ctx| {
+    ///     ctx.command("quit").run(|| {});
+    ///     ctx.otherwise_arg::<u32>().run(|&number| picked = Some(number));
+    /// });
+    /// assert_eq!(picked, Some(5));
+    ///
+    /// let quit = conso::test::run(&["quit"], commands);
+    /// assert_eq!(quit.state, conso::FinishedState::Okay { path: vec![String::from("quit")] });
+    ///
+    /// let excess = conso::test::run(&["5", "x"], commands);
+    /// assert!(excess.output.contains("\n5 x\n  ^ Excess arguments passed\n"));
+    /// assert!(matches!(excess.state, conso::FinishedState::Error { depth: 1, .. }));
+    /// ```
+    #[must_use = "Without using the return value, using this command will always yield an error"]
+    pub fn otherwise_arg<T: Arg<'input>>(&mut self) -> DataCommand<'_, 'input, T, Ret> {
+        self.data_command(unconstrained::<T>())
+    }
+
+    /// The segments that picked the commands leading here, not including their arguments. Empty
+    /// at the top level, and while building the help.
+    ///
+    /// ```
+    /// let mut context = String::new();
+    /// conso::parse(&["inv", "discard"], |ctx| {
+    ///     ctx.command("inv").sub_commands(|ctx| {
+    ///         let path = ctx.path().join(" > ");
+    ///         ctx.command("discard")
+    ///             .run(|| context = format!("in {} > discard", path));
+    ///     });
+    /// });
+    /// assert_eq!(context, "in inv > discard");
+    ///
+    /// let mut paths = Vec::new();
+    /// conso::parse(&["game", "inv", "list"], |ctx| {
+    ///     paths.push(ctx.path().join(" "));
+    ///     ctx.command("game").sub_commands(|ctx| {
+    ///         paths.push(ctx.path().join(" "));
+    ///         ctx.command("inv").sub_commands(|ctx| {
+    ///             paths.push(ctx.path().join(" "));
+    ///             ctx.command("list").run(|| {});
+    ///         });
+    ///     });
+    /// });
+    /// assert_eq!(paths, ["", "game", "game inv"]);
+    ///
+    /// let mut help_paths = Vec::new();
+    /// conso::render_help(|ctx| {
+    ///     ctx.command("inv").sub_commands(|ctx| {
+    ///         help_paths.push(ctx.path().len());
+    ///         ctx.command("list").run(|| {});
+    ///     });
+    /// });
+    /// assert!(!help_paths.is_empty() && help_paths.iter().all(|&len| len == 0));
+    /// ```
+    pub fn path(&self) -> &[&'input str] {
+        match &self.0 {
+            CtxInner::PickCommand { input, .. } => &input.path,
+            _ => &[],
+        }
+    }
+
+    /// Runs `handler` with all of the input if it didn't match any of the commands before this,
+    /// instead of that being an error. Useful for shell-like tools, where anything that isn't a
+    /// builtin is ran as an external program. It doesn't match empty input, and isn't shown in
+    /// the help.
+    ///
+    /// ```
+    /// let mut external = String::new();
+    /// conso::parse(&["git", "status"], |ctx| {
+    ///     ctx.command("cd")
+    ///         .arg::<String>()
+    ///         .run(|_| {});
+    ///
+    ///     ctx.fallback(|segments| external = segments.join(" "));
+    /// });
+    /// assert_eq!(external, "git status");
+    ///
+    /// fn shell(ctx: &mut conso::Ctx) {
+    ///     ctx.command("cd")
+    ///         .arg::<String>()
+    ///         .run(|_| {});
+    ///
+    ///     ctx.fallback(|segments| panic!("ran {:?}", segments));
+    /// }
+    ///
+    /// // Builtins win, even when they fail
+    /// let builtin = conso::test::run(&["cd", "src"], shell);
+    /// assert_eq!(builtin.state, conso::FinishedState::Okay { path: vec![String::from("cd")] });
+    /// let missing = conso::test::run(&["cd"], shell);
+    /// assert!(missing.output.contains("\ncd\n   ^ Missing argument, expected <string>\n"));
+    ///
+    /// // Empty input shows the help, which doesn't mention the fallback
+    /// let empty = conso::test::run(&[], shell);
+    /// assert_eq!(empty.state, conso::FinishedState::Help);
+    /// assert_eq!(empty.output, "cd <string>\n |  | Argument: <string>\n");
+    /// ```
+    pub fn fallback(&mut self, handler: impl FnOnce(&[&'input str]) -> Ret) {
+        self.data_command(unconstrained::<(&str, Vec<&str>)>())
+            .hidden()
+            .run(|(first, rest)| {
+                let mut segments = vec![*first];
+                segments.extend(rest);
+                handler(&segments)
+            });
+    }
+
+    /// Adds a command that runs if `constraint` matches the input. If several commands match,
+    /// the first one wins, and all the commands after it are skipped. In debug builds, a command
+    /// with the exact same name as an earlier one is warned about on stderr, once.
+    ///
+    /// ```
+    /// fn commands(ctx: &mut conso::Ctx, _: &mut conso::ControlFlow<()>) {
+    ///     ctx.command(conso::phrase(&["give", "up"])).run(|| {});
+    ///     ctx.command("give").arg::<String>().run(|_| {});
+    ///     ctx.command(conso::phrase(&["give", "up"])).run(|| {});
+    /// }
+    /// # // The warning goes to stderr, so this runs itself again to capture it
+    /// # if std::env::var_os("CONSO_DUPLICATE_PROBE").is_some() {
+    /// #     conso::user_loop_io("give up\ngive up\n".as_bytes(), std::io::sink(), commands);
+    /// #     return;
+    /// # }
+    /// # let probe = std::process::Command::new(std::env::current_exe().unwrap())
+    /// #     .env("CONSO_DUPLICATE_PROBE", "1")
+    /// #     .output()
+    /// #     .unwrap();
+    /// # let stderr = String::from_utf8(probe.stderr).unwrap();
+    ///
+    /// // Running two lines in a loop only warns once, and only about the phrase
+    /// assert_eq!(stderr, "Warning: there are several commands named `give up`, only the first one can run\n");
+    /// ```
     #[must_use = "Without using the return value, using this command will always yield an error"]
     pub fn command<C: ConstrainedArg<'input>>(&mut self, constraint: C) -> Command<'_, 'input, Ret> {
         Command(self.data_command(constraint).map(|_| ()))
     }
 
+    /// Adds a command that can be called by any of `names`, shown as `[n|north|up]` in the help.
+    /// The same as `command` with `conso::aliases`.
+    ///
+    /// ```
+    /// let mut moved = 0;
+    /// for name in ["n", "north", "up"] {
+    ///     conso::parse(&[name], |ctx| {
+    ///         ctx.aliases(&["n", "north", "up"])
+    ///             .description("Go north")
+    ///             .run(|| moved += 1);
+    ///     });
+    /// }
+    /// assert_eq!(moved, 3);
+    ///
+    /// let help = conso::test::run(&["help"], |ctx| {
+    ///     ctx.aliases(&["n", "north", "up"]).run(|| {});
+    /// });
+    /// assert!(help.output.contains("[n|north|up]"));
+    /// ```
+    #[must_use = "Without using the return value, using this command will always yield an error"]
+    pub fn aliases<'n>(&mut self, names: &'n [&'n str]) -> Command<'_, 'input, Ret> {
+        self.command(aliases(names))
+    }
+
+    /// Like `command`, but if `condition` is false the command doesn't exist; it never matches,
+    /// and isn't shown in the help information.
+    ///
+    /// ```
+    /// fn commands(debug: bool) -> impl FnMut(&mut conso::Ctx<'_, '_>) {
+    ///     move |ctx| {
+    ///         ctx.command("run").run(|| {});
+    ///         ctx.command_if(debug, "debug").run(|| {});
+    ///     }
+    /// }
+    ///
+    /// let enabled = conso::test::run(&["debug"], commands(true));
+    /// assert_eq!(enabled.state, conso::FinishedState::Okay { path: vec![String::from("debug")] });
+    /// assert!(conso::render_help(commands(true)).contains("debug"));
+    ///
+    /// let disabled = conso::test::run(&["debug"], commands(false));
+    /// assert!(disabled.output.contains("\ndebug\n^^^^^ Input did not match any wanted command\n"));
+    /// assert!(matches!(disabled.state, conso::FinishedState::Error { depth: 0, .. }));
+    /// assert!(!conso::render_help(commands(false)).contains("debug"));
+    /// ```
+    #[must_use = "Without using the return value, using this command will always yield an error"]
+    pub fn command_if<C: ConstrainedArg<'input>>(&mut self, condition: bool, constraint: C) -> Command<'_, 'input, Ret> {
+        if condition {
+            self.command(constraint)
+        } else {
+            Command(DataCommand(CommandInner::Skip))
+        }
+    }
+
     #[must_use = "Without using the return value, using this command will always yield an error"]
     pub fn data_command<C: ConstrainedArg<'input>>(&mut self, constraint: C) -> DataCommand<'_, 'input, C::Output, Ret> {
         match &mut self.0 {
@@ -254,10 +2147,24 @@ impl<'input, Ret> Ctx<'_, 'input, Ret> {
                 input,
                 output,
                 finished,
+                literals,
             } => {
+                #[cfg(debug_assertions)]
+                warn_duplicate_literals(literals, &constraint);
+                #[cfg(not(debug_assertions))]
+                let _ = literals;
+                if finished.is_some() {
+                    return DataCommand(CommandInner::Skip);
+                }
+
                 let mut input = input.clone();
+                let checkpoint = input.checkpoint();
                 match constraint.parse(&mut input) {
                     Some(data) => {
+                        let consumed = input.consumed_since(checkpoint).collect::<Vec<_>>();
+                        input.path.extend(consumed);
+                        input.command_depth = checkpoint.depth;
+                        input.arg_index = 0;
                         DataCommand(CommandInner::PickCommand {
                             input,
                             data: Some(data),
@@ -276,12 +2183,20 @@ impl<'input, Ret> Ctx<'_, 'input, Ret> {
                 help,
             } => {
                 let mut input = input.clone();
+                input.arg_index = 0;
                 if finished.is_none() && constraint.parse(&mut input).is_some() {
                     if input.finished() {
                         **finished = Some(FinishedState::Help);
 
+                        let start = help.mark();
+                        constraint.help(help);
+                        let usage_end = Some(help.output.len());
+                        help.indent();
                         DataCommand(CommandInner::BuildHelpInfo {
                             help,
+                            start,
+                            usage_end,
+                            finished: Some(finished),
                         })
                     } else {
                         DataCommand(CommandInner::BuildSubHelpInfo {
@@ -294,19 +2209,91 @@ impl<'input, Ret> Ctx<'_, 'input, Ret> {
                     DataCommand(CommandInner::Skip)
                 }
             }
+            CtxInner::CollectGlobalFlags { .. } => DataCommand(CommandInner::Skip),
+            CtxInner::BuildSchema { schema } => {
+                schema.sub_commands.push(CommandSchema {
+                    name: HelpFmt::render_line(|fmt| constraint.help(fmt)),
+                    literals: constraint.literals().into_iter().map(String::from).collect(),
+                    ..Default::default()
+                });
+                DataCommand(CommandInner::BuildSchema { parent: schema })
+            }
             CtxInner::BuildHelpInfo {
                 help,
             } => {
+                let start = help.mark();
                 constraint.help(help);
+                let usage_end = Some(help.output.len());
                 help.indent();
                 DataCommand(CommandInner::BuildHelpInfo {
                     help,
+                    start,
+                    usage_end,
+                    finished: None,
                 })
             }
         }
     }
 }
 
+/// The error for when `constraint` couldn't parse `input`, which was at `checkpoint` before trying.
+/// Points at the exact segment at fault if the constraint knew which one it was.
+fn arg_error<'a>(name: Option<&str>, checkpoint: Checkpoint<'a>, input: &Segments<'a>, constraint: &impl ConstrainedArg<'a>) -> FinishedState {
+    let depth = input.failed_at.unwrap_or(checkpoint.depth);
+    let (missing, details) = match &input.expected {
+        Some(expected) => (input.original.get(depth as usize).is_none(), format!("expected {}", expected)),
+        None => {
+            let remaining = input.unconsumed_since(checkpoint).collect::<Vec<_>>();
+            let details = constraint.error_message(&remaining).unwrap_or_else(|| {
+                format!("expected {}", HelpFmt::render_line(|fmt| constraint.help(fmt)))
+            });
+            (remaining.is_empty(), details)
+        }
+    };
+
+    let problem = if missing { "Missing argument" } else { "Invalid argument" };
+    let message = match name {
+        Some(name) => format!("{}: {}, {}", problem, name, details),
+        // Without a name, the position tells apart arguments of the same kind
+        None if input.arg_index > 0 => format!("{} #{}, {}", problem, input.arg_index + 1, details),
+        None => format!("{}, {}", problem, details),
+    };
+    FinishedState::Error { depth, message, help: None }
+}
+
+/// In debug builds, warns if `constraint` matches a literal command name that was already used,
+/// since a command like that could never run. Every name is only warned about once, even though
+/// the handler runs again for every line of a loop.
+#[cfg(debug_assertions)]
+fn warn_duplicate_literals<'a>(seen: &mut Vec<String>, constraint: &impl ConstrainedArg<'a>) {
+    thread_local! {
+        static WARNED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    // A phrase is only the same command as the exact same words, not as its first word alone
+    let names = match constraint.phrase() {
+        Some(words) => vec![words.join(" ")],
+        None => constraint.literals().into_iter().map(String::from).collect(),
+    };
+    for name in names {
+        if !seen.contains(&name) {
+            seen.push(name);
+            continue;
+        }
+
+        let first_time = WARNED.with_borrow_mut(|warned| {
+            let first_time = !warned.contains(&name);
+            if first_time {
+                warned.push(name.clone());
+            }
+            first_time
+        });
+        if first_time {
+            eprintln!("Warning: there are several commands named `{}`, only the first one can run", name);
+        }
+    }
+}
+
 pub struct Command<'r, 'input, Ret = ()>(DataCommand<'r, 'input, (), Ret>);
 
 pub struct DataCommand<'r, 'input, T, Ret = ()>(CommandInner<'r, 'input, T, Ret>);
@@ -326,6 +2313,16 @@ enum CommandInner<'r, 'input, T, Ret> {
     },
     BuildHelpInfo {
         help: &'r mut HelpFmt,
+        /// Where the help of this command started, so it can be taken back if the command is hidden.
+        start: HelpMark,
+        /// The end of the usage line of the command, where the arguments are added.
+        usage_end: Option<usize>,
+        /// Set if this is the command help was requested for.
+        finished: Option<&'r mut Option<FinishedState>>,
+    },
+    /// The command is the last one in `parent.sub_commands`.
+    BuildSchema {
+        parent: &'r mut CommandSchema,
     },
 }
 
@@ -334,6 +2331,60 @@ impl<'r, 'input, Ret> Command<'r, 'input, Ret> {
         Command(self.0.description(desc))
     }
 
+    pub fn description_raw(self, desc: &'static str) -> Self {
+        Command(self.0.description_raw(desc))
+    }
+
+    pub fn description_with(self, render: impl FnOnce(&mut HelpFmt)) -> Self {
+        Command(self.0.description_with(render))
+    }
+
+    pub fn example(self, example: &'static str) -> Self {
+        Command(self.0.example(example))
+    }
+
+    pub fn deprecated(self, note: &'static str) -> Self {
+        Command(self.0.deprecated(note))
+    }
+
+    pub fn hidden(self) -> Self {
+        Command(self.0.hidden())
+    }
+
+    /// Adds commands below this one. They're tried before any arguments added after this call,
+    /// so a subcommand wins over an argument that could also match the same input. If a
+    /// subcommand matches but fails, e.g. because it's missing an argument, that's an error,
+    /// rather than the input being tried as the argument.
+    ///
+    /// ```
+    /// let mut ran = Vec::new();
+    /// for input in [&["remote", "add", "origin", "url"][..], &["remote", "origin"], &["remote", "add", "origin"]] {
+    ///     conso::parse(input, |ctx| {
+    ///         ctx.command("remote")
+    ///             .sub_commands(|ctx| {
+    ///                 ctx.command("add")
+    ///                     .arg::<(String, String)>()
+    ///                     .run(|(name, _)| ran.push(format!("add {}", name)));
+    ///             })
+    ///             .arg::<String>()
+    ///             .run(|name| ran.push(format!("show {}", name)));
+    ///     });
+    /// }
+    /// assert_eq!(ran, ["add origin", "show origin"]);
+    /// ```
+    ///
+    /// The usage shown for an error in a sub command is under the path leading up to it.
+    ///
+    /// ```
+    /// let result = conso::test::run(&["game", "inv", "drop"], |ctx| {
+    ///     ctx.command("game").sub_commands(|ctx| {
+    ///         ctx.command("inv").sub_commands(|ctx| {
+    ///             ctx.command("list").run(|| {});
+    ///         });
+    ///     });
+    /// });
+    /// assert!(result.output.contains("Usage: \ngame:\n | inv\n |  | list\n"));
+    /// ```
     pub fn sub_commands(mut self, mut handler: impl FnMut(&mut Ctx<'_, 'input>)) -> Self {
         match &mut self.0.0 {
             CommandInner::PickCommand { input, finished, .. } => {
@@ -366,25 +2417,46 @@ impl<'r, 'input, Ret> Command<'r, 'input, Ret> {
                 });
                 handler(&mut ctx);
             }
+            CommandInner::BuildSchema { parent } => {
+                let mut ctx = Ctx(CtxInner::BuildSchema { schema: parent.last_command() });
+                handler(&mut ctx);
+            }
         }
 
         self
     }
 
+    /// Runs `handler` if the input ends exactly at this command. Meant to be combined with
+    /// `sub_commands`; the sub commands take precedence, and if the input continues without
+    /// matching any of them, an error is emitted instead.
+    pub fn default(mut self, handler: impl FnOnce() -> Ret) {
+        if let CommandInner::PickCommand { finished, input, output, .. } = &mut self.0.0 {
+            if finished.is_none() && input.finished() {
+                input.warn_deprecated();
+                **output = Some(handler());
+                **finished = Some(input.okay());
+            }
+        }
+    }
+
     pub fn user_loop(mut self, mut handler: impl FnMut(&mut Ctx<'_, '_>, &mut ControlFlow<'_, ()>)) {
         match &mut self.0.0 {
             CommandInner::PickCommand { finished, input, .. } => {
                 if finished.is_none() {
-                    if input.iter.next().is_some() {
+                    // Options may have been taken from after the excess arguments, so the error
+                    // points at the first segment that wasn't consumed rather than at the depth
+                    if let Some((depth, _)) = input.unconsumed().next() {
                         **finished = Some(FinishedState::Error {
-                            depth: input.depth,
+                            depth,
                             message: String::from("Excess arguments passed"),
                             help: None,
                         });
+                        return;
                     }
 
+                    input.warn_deprecated();
                     user_loop(handler);
-                    **finished = Some(FinishedState::Okay);
+                    **finished = Some(input.okay());
                 }
             }
             CommandInner::Skip => {}
@@ -395,12 +2467,13 @@ impl<'r, 'input, Ret> Command<'r, 'input, Ret> {
                         finished,
                         help: &mut **help,
                     });
-                    handler(&mut ctx, &mut ControlFlow { result: None });
+                    handler(&mut ctx, &mut ControlFlow { result: None, restart: None, ask: None });
                 }
             }
             CommandInner::BuildHelpInfo { help, .. } => {
                 help.push_paragraph("User loop");
             }
+            CommandInner::BuildSchema { .. } => {}
         }
     }
 
@@ -412,6 +2485,98 @@ impl<'r, 'input, Ret> Command<'r, 'input, Ret> {
         self.0.constrained_arg(sub_c).map(|(_, v)| v)
     }
 
+    pub fn arg_named<T: Arg<'input>>(self, name: &str) -> DataCommand<'r, 'input, T, Ret> {
+        self.constrained_arg_named(name, unconstrained::<T>())
+    }
+
+    /// See `DataCommand::option`.
+    ///
+    /// ```
+    /// let mut names = Vec::new();
+    /// for input in [&["greet", "--name", "bob"][..], &["greet", "--name="], &["greet"]] {
+    ///     conso::parse(input, |ctx| {
+    ///         ctx.command("greet")
+    ///             .option::<String>("name")
+    ///             .run(|name| names.push(name.clone()));
+    ///     });
+    /// }
+    /// assert_eq!(names, [Some(String::from("bob")), Some(String::new()), None]);
+    /// ```
+    pub fn option<V: for<'b> Arg<'b>>(self, name: &str) -> DataCommand<'r, 'input, Option<V>, Ret> {
+        self.constrained_option(name, unconstrained::<V>())
+    }
+
+    pub fn constrained_option<C, O>(self, name: &str, constraint: C) -> DataCommand<'r, 'input, Option<O>, Ret>
+    where
+        C: for<'b> ConstrainedArg<'b, Output = O>,
+    {
+        self.0.constrained_option(name, constraint).map(|(_, v)| v)
+    }
+
+    /// See `DataCommand::required_option`.
+    ///
+    /// ```
+    /// let mut config = None;
+    /// conso::parse(&["load", "--config", "app.toml"], |ctx| {
+    ///     ctx.command("load")
+    ///         .required_option::<String>("config")
+    ///         .run(|path| config = Some(path.clone()));
+    /// });
+    /// assert_eq!(config.as_deref(), Some("app.toml"));
+    ///
+    /// let result = conso::test::run(&["load"], |ctx| {
+    ///     ctx.command("load")
+    ///         .required_option::<String>("config")
+    ///         .run(|_| {});
+    /// });
+    /// assert!(result.output.starts_with("# Error\nload\n^^^^ Missing required option --config\n"));
+    /// assert!(result.output.contains("\nload --config <string>\n"));
+    /// assert!(matches!(result.state, conso::FinishedState::Error { depth: 0, .. }));
+    /// ```
+    pub fn required_option<V: for<'b> Arg<'b>>(self, name: &str) -> DataCommand<'r, 'input, V, Ret> {
+        self.constrained_required_option(name, unconstrained::<V>())
+    }
+
+    pub fn constrained_required_option<C, O>(self, name: &str, constraint: C) -> DataCommand<'r, 'input, O, Ret>
+    where
+        C: for<'b> ConstrainedArg<'b, Output = O>,
+    {
+        self.0.constrained_required_option(name, constraint).map(|(_, v)| v)
+    }
+
+    /// See `DataCommand::count_flag`.
+    ///
+    /// ```
+    /// let mut verbosity = None;
+    /// conso::parse(&["build", "-vv", "--verbose"], |ctx| {
+    ///     ctx.command("build")
+    ///         .count_flag('v', "verbose")
+    ///         .run(|&count| verbosity = Some(count));
+    /// });
+    /// assert_eq!(verbosity, Some(3));
+    /// ```
+    pub fn count_flag(self, short: char, long: &str) -> DataCommand<'r, 'input, u32, Ret> {
+        self.0.count_flag(short, long).map(|(_, v)| v)
+    }
+
+    /// See `DataCommand::exclusive_flags`.
+    ///
+    /// ```
+    /// let result = conso::test::run(&["export", "--json", "--yaml"], |ctx| {
+    ///     ctx.command("export")
+    ///         .exclusive_flags(&["json", "yaml"])
+    ///         .run(|_| {});
+    /// });
+    /// assert!(result.output.contains("             ^^^^^^ Only one of --json, --yaml can be given"));
+    /// ```
+    pub fn exclusive_flags(self, names: &'static [&'static str]) -> DataCommand<'r, 'input, Option<&'static str>, Ret> {
+        self.0.exclusive_flags(names).map(|(_, v)| v)
+    }
+
+    pub fn constrained_arg_named<SubC: ConstrainedArg<'input>>(self, name: &str, sub_c: SubC) -> DataCommand<'r, 'input, SubC::Output, Ret> {
+        self.0.constrained_arg_named(name, sub_c).map(|(_, v)| v)
+    }
+
     pub fn run(self, handler: impl FnOnce() -> Ret) {
         self.0.run(|()| handler());
     }
@@ -419,18 +2584,182 @@ impl<'r, 'input, Ret> Command<'r, 'input, Ret> {
 
 impl<'r, 'input, T, Ret> DataCommand<'r, 'input, T, Ret> {
     pub fn description(mut self, desc: &'static str) -> Self {
-        match self.0 {
-            CommandInner::BuildHelpInfo { ref mut help, .. } => {
+        match &mut self.0 {
+            CommandInner::BuildHelpInfo { help, .. } => {
                 help.small_indent();
                 help.push_paragraph(desc);
                 help.small_deindent();
             }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().description = Some(String::from(desc));
+            }
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Like `description`, but the text is shown exactly as it's given instead of being wrapped.
+    /// Useful for tables and other text that has to stay aligned.
+    ///
+    /// ```
+    /// let help = conso::test::run(&["help"], |ctx| {
+    ///     ctx.command("sizes")
+    ///         .description_raw("size  | width\nsmall | 10")
+    ///         .run(|| {});
+    /// });
+    /// assert_eq!(help.output, "sizes\n |  size  | width\n |  small | 10\n");
+    ///
+    /// // `description` would have joined the spaces
+    /// let wrapped = conso::test::run(&["help"], |ctx| {
+    ///     ctx.command("sizes")
+    ///         .description("size  | width\nsmall | 10")
+    ///         .run(|| {});
+    /// });
+    /// assert_eq!(wrapped.output, "sizes\n |  size | width\n |  small | 10\n");
+    /// ```
+    pub fn description_raw(mut self, desc: &'static str) -> Self {
+        match &mut self.0 {
+            CommandInner::BuildHelpInfo { help, .. } => {
+                help.small_indent();
+                help.push_preformatted(desc);
+                help.small_deindent();
+            }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().description = Some(String::from(desc));
+            }
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Like `description`, but the description is written by `render` when the help is shown,
+    /// so it can include things that change at runtime. `render` isn't called when parsing.
+    ///
+    /// ```
+    /// let inventory = vec!["sword", "shield"];
+    /// let help = conso::render_help(|ctx| {
+    ///     ctx.command("discard")
+    ///         .description_with(|fmt| {
+    ///             fmt.push_paragraph(&format!("Discard one of: {}", inventory.join(", ")));
+    ///         })
+    ///         .run(|| {});
+    /// });
+    /// assert!(help.contains("Discard one of: sword, shield"));
+    /// ```
+    pub fn description_with(mut self, render: impl FnOnce(&mut HelpFmt)) -> Self {
+        match &mut self.0 {
+            CommandInner::BuildHelpInfo { help, .. } => {
+                help.small_indent();
+                render(help);
+                help.small_deindent();
+            }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().description = Some(HelpFmt::render_line(render));
+            }
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Adds an example of how the command can be used to the help information.
+    pub fn example(mut self, example: &'static str) -> Self {
+        match &mut self.0 {
+            CommandInner::BuildHelpInfo { help, .. } => {
+                help.small_indent();
+                help.push_word("Example:");
+                help.push_paragraph(example);
+                help.small_deindent();
+            }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().examples.push(String::from(example));
+            }
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Marks the command as deprecated, while still letting it be ran. Right before it runs,
+    /// `note` is printed to stderr as a warning, and the help shows it next to the command.
+    ///
+    /// ```
+    /// fn commands(ctx: &mut conso::Ctx) {
+    ///     ctx.command("walk")
+    ///         .deprecated("use `move` instead")
+    ///         .run(|| eprintln!("Walking"));
+    /// }
+    ///
+    /// let help = conso::render_help(commands);
+    /// assert!(help.contains("(deprecated: use `move` instead)"));
+    /// # // The warning goes to stderr, so this runs itself again to capture it
+    /// # if std::env::var_os("CONSO_DEPRECATED_PROBE").is_some() {
+    /// #     conso::parse(&["walk"], commands);
+    /// #     return;
+    /// # }
+    /// # let probe = std::process::Command::new(std::env::current_exe().unwrap())
+    /// #     .env("CONSO_DEPRECATED_PROBE", "1")
+    /// #     .output()
+    /// #     .unwrap();
+    /// # let stderr = String::from_utf8(probe.stderr).unwrap();
+    ///
+    /// // The warning is shown before the command runs
+    /// assert_eq!(stderr, "Warning: this command is deprecated, use `move` instead\nWalking\n");
+    /// ```
+    pub fn deprecated(mut self, note: &'static str) -> Self {
+        match &mut self.0 {
+            CommandInner::PickCommand { input, .. } => {
+                input.deprecated = Some(note);
+            }
+            CommandInner::BuildHelpInfo { help, .. } => {
+                help.small_indent();
+                help.push_paragraph(&format!("(deprecated: {})", note));
+                help.small_deindent();
+            }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().deprecated = Some(String::from(note));
+            }
             _ => {}
         }
 
         self
     }
 
+    /// Hides the command from the help information, while still letting it be ran.
+    /// Useful for debug commands or deprecated aliases. Asking for its help gives the same
+    /// error as for a command that doesn't exist.
+    ///
+    /// ```
+    /// fn commands(ctx: &mut conso::Ctx) {
+    ///     ctx.command("debug").hidden().run(|| {});
+    ///     ctx.command("look").run(|| {});
+    /// }
+    ///
+    /// let hidden = conso::test::run(&["help", "debug"], commands);
+    /// let missing = conso::test::run(&["help", "dbg"], commands);
+    /// assert!(hidden.output.ends_with("Argument did not match any possible command\n\nUsage: \nlook\n"));
+    /// assert_eq!(hidden.output.replace("debug", "dbg").replace("^^^^^", "^^^"), missing.output);
+    /// ```
+    pub fn hidden(mut self) -> Self {
+        match std::mem::replace(&mut self.0, CommandInner::Skip) {
+            CommandInner::BuildSubHelpInfo { .. } => {}
+            CommandInner::BuildHelpInfo { help, start, finished, .. } => {
+                help.rewind(start);
+                if let Some(finished) = finished {
+                    *finished = None;
+                }
+            }
+            CommandInner::BuildSchema { parent } => {
+                parent.sub_commands.pop();
+            }
+            inner => self.0 = inner,
+        }
+
+        self
+    }
+
     fn map<OutT>(mut self, mapper: impl FnOnce(T) -> OutT) -> DataCommand<'r, 'input, OutT, Ret> {
         match std::mem::replace(&mut self.0, CommandInner::Skip) {
             CommandInner::PickCommand { input, data, finished, output } => {
@@ -449,11 +2778,15 @@ impl<'r, 'input, T, Ret> DataCommand<'r, 'input, T, Ret> {
                     finished,
                 })
             }
-            CommandInner::BuildHelpInfo { help } => {
+            CommandInner::BuildHelpInfo { help, start, usage_end, finished } => {
                 DataCommand(CommandInner::BuildHelpInfo {
                     help,
+                    start,
+                    usage_end,
+                    finished,
                 })
             }
+            CommandInner::BuildSchema { parent } => DataCommand(CommandInner::BuildSchema { parent }),
         }
     }
 
@@ -461,13 +2794,293 @@ impl<'r, 'input, T, Ret> DataCommand<'r, 'input, T, Ret> {
         self.constrained_arg(unconstrained::<V>())
     }
 
-    pub fn constrained_arg<SubC: ConstrainedArg<'input>>(mut self, sub_c: SubC) -> DataCommand<'r, 'input, (T, SubC::Output), Ret> {
-        match std::mem::replace(&mut self.0, CommandInner::Skip) {
-            CommandInner::PickCommand { finished, data, mut input, output } => {
-                if finished.is_none() {
-                    let orig_depth = input.depth;
+    /// Adds an argument that has to match `sub_c`. If it's missing or invalid and isn't the first
+    /// argument, the error says which one it was, like `Invalid argument #2`. Use
+    /// `constrained_arg_named` to give it a proper name instead.
+    ///
+    /// ```
+    /// fn commands(ctx: &mut conso::Ctx) {
+    ///     ctx.command("multiply")
+    ///         .arg::<i32>()
+    ///         .arg::<i32>()
+    ///         .run(|(a, b)| println!("{}", a * b));
+    /// }
+    ///
+    /// let result = conso::test::run(&["multiply", "3", "x"], commands);
+    /// assert!(result.output.contains("\nmultiply 3 x\n           ^ Invalid argument #2, expected <integer>\n"));
+    /// assert!(matches!(result.state, conso::FinishedState::Error { depth: 2, .. }));
+    ///
+    /// let missing = conso::test::run(&["multiply", "3"], commands);
+    /// assert!(missing.output.contains("\nmultiply 3\n           ^ Missing argument #2, expected <integer>\n"));
+    ///
+    /// // The first argument isn't numbered
+    /// let first = conso::test::run(&["multiply", "x", "3"], commands);
+    /// assert!(first.output.contains("\nmultiply x 3\n         ^ Invalid argument, expected <integer>\n"));
+    ///
+    /// let named = conso::test::run(&["multiply", "2", "x"], |ctx| {
+    ///     ctx.command("multiply")
+    ///         .arg_named::<u32>("first number")
+    ///         .arg_named::<u32>("second number")
+    ///         .run(|_| {});
+    /// });
+    /// assert!(named.output.contains("\nmultiply 2 x\n           ^ Invalid argument: second number, expected <unsigned integer>\n"));
+    /// ```
+    pub fn constrained_arg<SubC: ConstrainedArg<'input>>(self, sub_c: SubC) -> DataCommand<'r, 'input, (T, SubC::Output), Ret> {
+        self.constrained_arg_inner(None, sub_c)
+    }
+
+    /// Like `arg`, but the argument is given a name, which is used in the help information,
+    /// and in the error if the argument is missing or invalid.
+    pub fn arg_named<V: Arg<'input>>(self, name: &str) -> DataCommand<'r, 'input, (T, V), Ret> {
+        self.constrained_arg_named(name, unconstrained::<V>())
+    }
+
+    /// Like `constrained_arg`, but the argument is given a name, which is used in the help
+    /// information, and in the error if the argument is missing or invalid.
+    pub fn constrained_arg_named<SubC: ConstrainedArg<'input>>(self, name: &str, sub_c: SubC) -> DataCommand<'r, 'input, (T, SubC::Output), Ret> {
+        self.constrained_arg_inner(Some(name), sub_c)
+    }
+
+    /// Adds an option like `--name value` or `--name=value`, that can be given anywhere after the
+    /// command, and is `None` if it isn't. Options have to be added before the arguments, or the
+    /// arguments could take the option as their input.
+    ///
+    /// ```
+    /// let mut tags = Some(Vec::new());
+    /// conso::parse(&["post"], |ctx| {
+    ///     ctx.command("post")
+    ///         .option::<Vec<String>>("tags")
+    ///         .run(|t| tags = t.clone());
+    /// });
+    /// assert_eq!(tags, None);
+    /// ```
+    pub fn option<V: for<'b> Arg<'b>>(self, name: &str) -> DataCommand<'r, 'input, (T, Option<V>), Ret> {
+        self.constrained_option(name, unconstrained::<V>())
+    }
+
+    /// Like `option`, but the value has to match `constraint`.
+    pub fn constrained_option<C, O>(self, name: &str, constraint: C) -> DataCommand<'r, 'input, (T, Option<O>), Ret>
+    where
+        C: for<'b> ConstrainedArg<'b, Output = O>,
+    {
+        self.option_inner(name, constraint, false)
+    }
+
+    /// Like `option`, but the option has to be given, so there's always a value.
+    pub fn required_option<V: for<'b> Arg<'b>>(self, name: &str) -> DataCommand<'r, 'input, (T, V), Ret> {
+        self.constrained_required_option(name, unconstrained::<V>())
+    }
+
+    /// Like `required_option`, but the value has to match `constraint`.
+    pub fn constrained_required_option<C, O>(self, name: &str, constraint: C) -> DataCommand<'r, 'input, (T, O), Ret>
+    where
+        C: for<'b> ConstrainedArg<'b, Output = O>,
+    {
+        self.option_inner(name, constraint, true)
+            .map(|(data, value)| (data, value.expect("A missing required option is an error")))
+    }
+
+    /// Adds a flag that can be given any number of times anywhere after the command, either as
+    /// `--long` or as `-short`, where the short form can be repeated like `-vvv`. Gives back how
+    /// many times it was given in total. Like options, it has to be added before the arguments.
+    pub fn count_flag(mut self, short: char, long: &str) -> DataCommand<'r, 'input, (T, u32), Ret> {
+        match std::mem::replace(&mut self.0, CommandInner::Skip) {
+            CommandInner::PickCommand { finished, data, mut input, output } => {
+                if finished.is_some() {
+                    return DataCommand(CommandInner::PickCommand { finished, data: None, output, input });
+                }
+
+                let count = input.take_flag_count(short, long);
+                DataCommand(CommandInner::PickCommand {
+                    finished,
+                    data: data.map(|data| (data, count)),
+                    output,
+                    input,
+                })
+            }
+            CommandInner::Skip => DataCommand(CommandInner::Skip),
+            CommandInner::BuildSubHelpInfo { mut input, help, finished } => {
+                input.take_flag_count(short, long);
+                DataCommand(CommandInner::BuildSubHelpInfo { finished, help, input })
+            }
+            CommandInner::BuildHelpInfo { help, start, mut usage_end, finished } => {
+                if let Some(usage_end) = &mut usage_end {
+                    help.push_usage(usage_end, |fmt| fmt.push_word(&format!("[-{}|--{}]...", short, long)));
+                }
+
+                help.indent();
+                help.push_word("Flag");
+                help.push_word(&format!("-{}, --{}:", short, long));
+                help.push_word("can be repeated");
+                help.deindent();
+                DataCommand(CommandInner::BuildHelpInfo {
+                    help,
+                    start,
+                    usage_end,
+                    finished,
+                })
+            }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().options.push(ArgSchema {
+                    name: Some(String::from(long)),
+                    help: format!("-{}", short),
+                });
+                DataCommand(CommandInner::BuildSchema { parent })
+            }
+        }
+    }
+
+    /// Adds a group of flags like `--json` and `--yaml` where at most one can be given, anywhere
+    /// after the command. Gives back the name of the one that was given, if any. Giving more than
+    /// one of them is an error pointing at the second. Like options, it has to be added before
+    /// the arguments.
+    pub fn exclusive_flags(mut self, names: &'static [&'static str]) -> DataCommand<'r, 'input, (T, Option<&'static str>), Ret> {
+        match std::mem::replace(&mut self.0, CommandInner::Skip) {
+            CommandInner::PickCommand { finished, data, mut input, output } => {
+                if finished.is_some() {
+                    return DataCommand(CommandInner::PickCommand { finished, data: None, output, input });
+                }
+
+                match input.take_exclusive_flags(names) {
+                    Ok(given) => {
+                        DataCommand(CommandInner::PickCommand {
+                            finished,
+                            data: data.map(|data| (data, given)),
+                            output,
+                            input,
+                        })
+                    }
+                    Err((depth, message)) => {
+                        *finished = Some(FinishedState::Error { depth, message, help: None });
+                        DataCommand(CommandInner::Skip)
+                    }
+                }
+            }
+            CommandInner::Skip => DataCommand(CommandInner::Skip),
+            CommandInner::BuildSubHelpInfo { mut input, help, finished } => {
+                // Conflicts are reported when running the command, the help is shown either way
+                let _ = input.take_exclusive_flags(names);
+                DataCommand(CommandInner::BuildSubHelpInfo { finished, help, input })
+            }
+            CommandInner::BuildHelpInfo { help, start, mut usage_end, finished } => {
+                let flags = names.iter().map(|name| format!("--{}", name)).collect::<Vec<_>>();
+                if let Some(usage_end) = &mut usage_end {
+                    help.push_usage(usage_end, |fmt| fmt.push_word(&format!("[{}]", flags.join("|"))));
+                }
+
+                help.indent();
+                help.push_word("Flags");
+                help.push_word(&format!("{}:", flags.join(", ")));
+                help.push_word("only one can be given");
+                help.deindent();
+                DataCommand(CommandInner::BuildHelpInfo {
+                    help,
+                    start,
+                    usage_end,
+                    finished,
+                })
+            }
+            CommandInner::BuildSchema { parent } => {
+                for name in names {
+                    parent.last_command().options.push(ArgSchema {
+                        name: Some(String::from(*name)),
+                        help: String::new(),
+                    });
+                }
+                DataCommand(CommandInner::BuildSchema { parent })
+            }
+        }
+    }
+
+    fn option_inner<C, O>(mut self, name: &str, constraint: C, required: bool) -> DataCommand<'r, 'input, (T, Option<O>), Ret>
+    where
+        C: for<'b> ConstrainedArg<'b, Output = O>,
+    {
+        match std::mem::replace(&mut self.0, CommandInner::Skip) {
+            CommandInner::PickCommand { finished, data, mut input, output } => {
+                if finished.is_some() {
+                    return DataCommand(CommandInner::PickCommand { finished, data: None, output, input });
+                }
+
+                // Constraints with a default, like `with_default`, still give a value when the option is missing
+                match input.take_option(name, &constraint).map(|value| value.or_else(|| constraint.default_value())) {
+                    Ok(None) if required => {
+                        *finished = Some(FinishedState::Error {
+                            depth: input.command_depth,
+                            message: format!("Missing required option --{}", name),
+                            help: None,
+                        });
+                        DataCommand(CommandInner::Skip)
+                    }
+                    Ok(value) => {
+                        DataCommand(CommandInner::PickCommand {
+                            finished,
+                            data: data.map(|data| (data, value)),
+                            output,
+                            input,
+                        })
+                    }
+                    Err((depth, message)) => {
+                        *finished = Some(FinishedState::Error { depth, message, help: None });
+                        DataCommand(CommandInner::Skip)
+                    }
+                }
+            }
+            CommandInner::Skip => DataCommand(CommandInner::Skip),
+            CommandInner::BuildSubHelpInfo { mut input, help, finished } => {
+                if finished.is_some() {
+                    return DataCommand(CommandInner::BuildSubHelpInfo { finished, help, input });
+                }
+
+                match input.take_option(name, &constraint) {
+                    Ok(_) => DataCommand(CommandInner::BuildSubHelpInfo { finished, help, input }),
+                    Err((depth, message)) => {
+                        *finished = Some(FinishedState::Error { depth, message, help: None });
+                        DataCommand(CommandInner::Skip)
+                    }
+                }
+            }
+            CommandInner::BuildHelpInfo { help, start, mut usage_end, finished } => {
+                let value = HelpFmt::render_line(|fmt| constraint.help(fmt));
+                if let Some(usage_end) = &mut usage_end {
+                    let usage = if required {
+                        format!("--{} {}", name, value)
+                    } else {
+                        format!("[--{} {}]", name, value)
+                    };
+                    help.push_usage(usage_end, |fmt| fmt.push_word(&usage));
+                }
+
+                help.indent();
+                help.push_word("Option");
+                help.push_word(&format!("--{}:", name));
+                constraint.help(help);
+                help.deindent();
+                DataCommand(CommandInner::BuildHelpInfo {
+                    help,
+                    start,
+                    usage_end,
+                    finished,
+                })
+            }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().options.push(ArgSchema {
+                    name: Some(String::from(name)),
+                    help: HelpFmt::render_line(|fmt| constraint.help(fmt)),
+                });
+                DataCommand(CommandInner::BuildSchema { parent })
+            }
+        }
+    }
+
+    fn constrained_arg_inner<SubC: ConstrainedArg<'input>>(mut self, name: Option<&str>, sub_c: SubC) -> DataCommand<'r, 'input, (T, SubC::Output), Ret> {
+        match std::mem::replace(&mut self.0, CommandInner::Skip) {
+            CommandInner::PickCommand { finished, data, mut input, output } => {
+                if finished.is_none() {
+                    let checkpoint = input.checkpoint();
                     match sub_c.parse(&mut input) {
                         Some(new_data) => {
+                            input.arg_index += 1;
                             DataCommand(CommandInner::PickCommand {
                                 finished,
                                 data: data.map(|data| (data, new_data)),
@@ -476,11 +3089,7 @@ impl<'r, 'input, T, Ret> DataCommand<'r, 'input, T, Ret> {
                             })
                         }
                         None => {
-                            *finished = Some(FinishedState::Error {
-                                depth: orig_depth,
-                                message: String::from("Invalid argument"),
-                                help: None,
-                            });
+                            *finished = Some(arg_error(name, checkpoint, &input, &sub_c));
 
                             DataCommand(CommandInner::Skip)
                         }
@@ -497,9 +3106,10 @@ impl<'r, 'input, T, Ret> DataCommand<'r, 'input, T, Ret> {
             CommandInner::Skip => DataCommand(CommandInner::Skip),
             CommandInner::BuildSubHelpInfo { mut input, help, finished } => {
                 if finished.is_none() {
-                    let orig_depth = input.depth;
+                    let checkpoint = input.checkpoint();
                     match sub_c.parse(&mut input) {
                         Some(_) => {
+                            input.arg_index += 1;
                             DataCommand(CommandInner::BuildSubHelpInfo {
                                 help,
                                 finished,
@@ -507,11 +3117,7 @@ impl<'r, 'input, T, Ret> DataCommand<'r, 'input, T, Ret> {
                             })
                         }
                         None => {
-                            *finished = Some(FinishedState::Error {
-                                depth: orig_depth,
-                                message: String::from("Invalid argument"),
-                                help: None,
-                            });
+                            *finished = Some(arg_error(name, checkpoint, &input, &sub_c));
 
                             DataCommand(CommandInner::Skip)
                         }
@@ -524,39 +3130,137 @@ impl<'r, 'input, T, Ret> DataCommand<'r, 'input, T, Ret> {
                     })
                 }
             }
-            CommandInner::BuildHelpInfo { help } => {
+            CommandInner::BuildHelpInfo { help, start, mut usage_end, finished } => {
+                if let Some(usage_end) = &mut usage_end {
+                    help.push_usage(usage_end, |fmt| sub_c.help(fmt));
+                }
+
                 help.indent();
-                help.push_word("Argument:");
+                match name {
+                    Some(name) => {
+                        help.push_word("Argument");
+                        help.push_word(&format!("{}:", name));
+                    }
+                    None => help.push_word("Argument:"),
+                }
                 sub_c.help(help);
                 help.deindent();
                 DataCommand(CommandInner::BuildHelpInfo {
                     help,
+                    start,
+                    usage_end,
+                    finished,
                 })
             }
+            CommandInner::BuildSchema { parent } => {
+                parent.last_command().args.push(ArgSchema {
+                    name: name.map(String::from),
+                    help: HelpFmt::render_line(|fmt| sub_c.help(fmt)),
+                });
+                DataCommand(CommandInner::BuildSchema { parent })
+            }
+        }
+    }
+
+    /// Hands the rest of the input over to `handler`, which gets the arguments and picks the
+    /// commands that follow based on them, e.g. the commands of a plugin chosen by name. The
+    /// arguments are only known while parsing, so the help can't list those commands.
+    ///
+    /// ```
+    /// fn greeter(ctx: &mut conso::Ctx) {
+    ///     ctx.command("greet").run(|| println!("Hello!"));
+    /// }
+    ///
+    /// let mut ran = None;
+    /// conso::parse(&["plugin", "greeter", "greet"], |ctx| {
+    ///     ctx.command("plugin")
+    ///         .arg::<String>()
+    ///         .sub_parser(|name, ctx| {
+    ///             ran = Some(name.clone());
+    ///             if name == "greeter" {
+    ///                 greeter(ctx);
+    ///             }
+    ///         });
+    /// });
+    /// assert_eq!(ran.as_deref(), Some("greeter"));
+    /// ```
+    pub fn sub_parser(mut self, mut handler: impl FnMut(&T, &mut Ctx<'_, 'input>)) {
+        match &mut self.0 {
+            CommandInner::PickCommand { finished, data, input, .. } => {
+                if finished.is_none() {
+                    let data = data.as_ref().expect("If our data is none we should be in a finished state");
+                    pick_sub_command(input, finished, |ctx| handler(data, ctx), true);
+                }
+            }
+            CommandInner::Skip => {}
+            CommandInner::BuildSubHelpInfo { input, finished, .. } => {
+                if finished.is_none() {
+                    **finished = Some(FinishedState::Error {
+                        depth: input.depth,
+                        message: String::from("The commands here depend on the arguments, so there's no help for them"),
+                        help: None,
+                    });
+                }
+            }
+            CommandInner::BuildHelpInfo { help, usage_end, .. } => {
+                if let Some(usage_end) = usage_end {
+                    help.push_usage(usage_end, |fmt| fmt.push_word("..."));
+                }
+            }
+            CommandInner::BuildSchema { .. } => {}
         }
     }
 
+    /// Runs `handler` with the parsed arguments if this is the command that was picked. Any
+    /// segments left over after the arguments are an error, pointing at the first of them.
+    ///
+    /// ```
+    /// let greet = conso::test::run(&["greet", "extra"], |ctx| {
+    ///     ctx.command("greet").run(|| {});
+    /// });
+    /// assert!(greet.output.contains("greet extra\n      ^^^^^ Excess arguments passed\n"));
+    ///
+    /// let multiply = conso::test::run(&["multiply", "1", "2", "3"], |ctx| {
+    ///     ctx.command("multiply")
+    ///         .arg::<u32>()
+    ///         .arg::<u32>()
+    ///         .run(|(a, b)| println!("{}", a * b));
+    /// });
+    /// assert!(multiply.output.contains("multiply 1 2 3\n             ^ Excess arguments passed\n"));
+    /// assert!(matches!(multiply.state, conso::FinishedState::Error { depth: 3, .. }));
+    ///
+    /// let named = conso::test::run(&["greet", "--name", "bob", "extra"], |ctx| {
+    ///     ctx.command("greet")
+    ///         .option::<String>("name")
+    ///         .run(|_| {});
+    /// });
+    /// assert!(named.output.contains("greet --name bob extra\n                 ^^^^^ Excess arguments passed\n"));
+    /// ```
     pub fn run(mut self, handler: impl FnOnce(&T) -> Ret) {
         match &mut self.0 {
             CommandInner::PickCommand { finished, data, input, output, .. } => {
                 if finished.is_none() {
-                    if input.iter.next().is_some() {
+                    // Options may have been taken from after the excess arguments, so the error
+                    // points at the first segment that wasn't consumed rather than at the depth
+                    if let Some((depth, _)) = input.unconsumed().next() {
                         **finished = Some(FinishedState::Error {
-                            depth: input.depth,
+                            depth,
                             message: String::from("Excess arguments passed"),
                             help: None,
                         });
                         return;
                     }
 
+                    input.warn_deprecated();
                     let result = handler(data.as_ref().expect("If our data is none we should be in a finished state"));
                     **output = Some(result);
-                    **finished = Some(FinishedState::Okay);
+                    **finished = Some(input.okay());
                 }
             }
             CommandInner::Skip => {}
             CommandInner::BuildSubHelpInfo { .. } => {}
             CommandInner::BuildHelpInfo { .. } => {}
+            CommandInner::BuildSchema { .. } => {}
         }
     }
 }
@@ -583,9 +3287,10 @@ impl<'input, T, Ret> Drop for DataCommand<'_, 'input, T, Ret> {
                     });
                 }
             }
-            CommandInner::BuildHelpInfo { help } => {
+            CommandInner::BuildHelpInfo { help, .. } => {
                 help.deindent();
             }
+            CommandInner::BuildSchema { .. } => {}
         }
     }
 }
@@ -597,7 +3302,17 @@ pub struct HelpFmt {
     current_line_length: usize,
     max_length: usize,
     empty_line: bool,
-    output: Option<String>,
+    output: String,
+}
+
+/// A position in the output of a `HelpFmt` that it can be rewound to.
+#[derive(Clone, Copy)]
+struct HelpMark {
+    length: usize,
+    indent: u32,
+    small_indent: u32,
+    current_line_length: usize,
+    empty_line: bool,
 }
 
 impl Default for HelpFmt {
@@ -609,19 +3324,53 @@ impl Default for HelpFmt {
             current_line_length: 0,
             max_length: 100,
             empty_line: true,
-            output: None,
+            output: String::new(),
         }
     }
 }
 
 impl HelpFmt {
     fn push_completely_raw(&mut self, stuff: &str) {
-        match self.output {
-            Some(ref mut string) => string.push_str(stuff),
-            None => print!("{}", stuff),
+        self.output.push_str(stuff);
+    }
+
+    fn mark(&self) -> HelpMark {
+        HelpMark {
+            length: self.output.len(),
+            indent: self.indent,
+            small_indent: self.small_indent,
+            current_line_length: self.current_line_length,
+            empty_line: self.empty_line,
         }
     }
 
+    /// Renders something on a single line, without any line breaks.
+    fn render_line(render: impl FnOnce(&mut HelpFmt)) -> String {
+        let mut fmt = HelpFmt {
+            max_length: usize::MAX,
+            ..Default::default()
+        };
+        render(&mut fmt);
+        fmt.output
+    }
+
+    /// Renders something on a single line, and inserts it at `position` in an already written
+    /// usage line, moving `position` to the end of it.
+    fn push_usage(&mut self, position: &mut usize, render: impl FnOnce(&mut HelpFmt)) {
+        let usage = format!(" {}", HelpFmt::render_line(render));
+        self.output.insert_str(*position, &usage);
+        *position += usage.len();
+    }
+
+    /// Removes everything written since `mark` was created.
+    fn rewind(&mut self, mark: HelpMark) {
+        self.output.truncate(mark.length);
+        self.indent = mark.indent;
+        self.small_indent = mark.small_indent;
+        self.current_line_length = mark.current_line_length;
+        self.empty_line = mark.empty_line;
+    }
+
     fn print_indent(&mut self) {
         self.empty_line = false;
         for _ in 0..self.indent {
@@ -641,242 +3390,1771 @@ impl HelpFmt {
         self.line_break();
     }
 
-    pub fn deindent(&mut self) {
-        if self.indent != 0 {
-            self.indent -= 1;
-            self.small_indent = 0;
-        }
-        self.line_break();
+    pub fn deindent(&mut self) {
+        if self.indent != 0 {
+            self.indent -= 1;
+            self.small_indent = 0;
+        }
+        self.line_break();
+    }
+
+    pub fn small_indent(&mut self) {
+        self.small_indent += 1;
+        self.line_break();
+    }
+
+    pub fn small_deindent(&mut self) {
+        if self.small_indent != 0 {
+            self.small_indent -= 1;
+        }
+        self.line_break();
+    }
+
+    pub fn push_raw_str(&mut self, string: &str) {
+        if self.empty_line {
+            self.print_indent();
+        }
+
+        self.push_completely_raw(string);
+        self.current_line_length += string.chars().count();
+    }
+
+    /// Pushes a word, moving it to the next line if it doesn't fit on the current one. Words
+    /// longer than a whole line are kept intact, and overflow it.
+    ///
+    /// ```
+    /// let url = format!("https://example.com/{}", "a".repeat(100));
+    /// let description = format!("See {} for more information about it", url).leak();
+    /// let help = conso::render_help(|ctx| {
+    ///     ctx.command("docs")
+    ///         .description(description)
+    ///         .run(|| {});
+    /// });
+    /// let lines = help.lines().collect::<Vec<_>>();
+    /// assert_eq!(lines, ["docs", " |  See", &*format!(" |  {}", url), " |  for more information about it"]);
+    /// ```
+    pub fn push_word(&mut self, word: &str) {
+        if !self.empty_line {
+            if self.current_line_length + 1 + word.chars().count() > self.max_length {
+                self.line_break();
+            } else {
+                self.push_raw_str(" ");
+            }
+        }
+
+        self.push_raw_str(word);
+    }
+
+    /// Pushes text that is wrapped to fit the line length. Line breaks are kept, and blank lines
+    /// separate paragraphs with an empty line.
+    pub fn push_paragraph(&mut self, string: &str) {
+        let lines = string.lines().collect::<Vec<_>>();
+        let start = lines.iter().position(|line| !line.trim().is_empty()).unwrap_or(lines.len());
+        let end = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(start, |i| i + 1);
+
+        let mut in_gap = false;
+        for (i, line) in lines[start..end].iter().enumerate() {
+            if line.trim().is_empty() {
+                // Several blank lines in a row still only make a single gap
+                if !in_gap {
+                    self.line_break();
+                    self.print_indent();
+                    in_gap = true;
+                }
+                continue;
+            }
+
+            if i > 0 {
+                self.line_break();
+            }
+            in_gap = false;
+
+            for word in line.split_whitespace() {
+                self.push_word(word);
+            }
+        }
+    }
+
+    /// Pushes text exactly as it's given, like a table or a code sample, without wrapping it.
+    /// Only the indentation is added to the start of each line.
+    pub fn push_preformatted(&mut self, string: &str) {
+        self.line_break();
+        for line in string.lines() {
+            self.print_indent();
+            self.push_completely_raw(line);
+            self.current_line_length += line.chars().count();
+            self.line_break();
+        }
+    }
+
+    pub fn line_break(&mut self) {
+        if !self.empty_line {
+            self.push_completely_raw("\n");
+            self.empty_line = true;
+            self.current_line_length = 0;
+        }
+    }
+}
+
+pub struct ControlFlow<'a, T> {
+    result: Option<&'a mut Option<T>>,
+    restart: Option<&'a mut bool>,
+    /// Shows a prompt and reads the answer, if the loop reads from somewhere other than stdin.
+    ask: Option<&'a mut Ask<'a>>,
+}
+
+/// Shows a prompt and adds a line of input to the end of the string, like `append_line`.
+type Ask<'a> = dyn FnMut(&str, &mut String) -> std::io::Result<usize> + 'a;
+
+impl<T> ControlFlow<'_, T> {
+    pub fn quit(&mut self, value: T) {
+        if let Some(result) = &mut self.result {
+            **result = Some(value);
+        }
+    }
+
+    /// Abandons the current input and prompts the user for the next one, without treating
+    /// the input as an error even if no command ran. Return from the handler after calling
+    /// this, so the rest of it is skipped.
+    pub fn restart(&mut self) {
+        if let Some(restart) = &mut self.restart {
+            **restart = true;
+        }
+    }
+
+    /// Like `confirm`, but asks through the input and output of the loop, so that it works with
+    /// `user_loop_io` too.
+    ///
+    /// ```
+    /// let mut output = Vec::new();
+    /// let mut reset = false;
+    /// conso::user_loop_io("reset\ny\nquit\n".as_bytes(), &mut output, |ctx, control_flow| {
+    ///     ctx.command("reset")
+    ///         .run(|| reset = control_flow.confirm("Delete everything?"));
+    ///     ctx.command("quit")
+    ///         .run(|| control_flow.quit(()));
+    /// });
+    /// assert!(reset);
+    /// assert_eq!(String::from_utf8(output).unwrap(), "~> Delete everything? [y/N] ~> ");
+    /// ```
+    pub fn confirm(&mut self, prompt: &str) -> bool {
+        let prompt = format!("{} [y/N] ", prompt);
+        let mut input = String::new();
+        let read = match &mut self.ask {
+            Some(ask) => ask(&prompt, &mut input),
+            None => read_line(&prompt, &mut input),
+        };
+        // An answer that can't be read is a no, like any other answer that isn't yes
+        read.is_ok() && is_yes(&input)
+    }
+}
+
+pub trait Arg<'a> {
+    fn help(fmt: &mut HelpFmt);
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized;
+}
+
+/// An optional argument. If `T` doesn't match, nothing is consumed, so it's fine for the input to
+/// end here. A segment that doesn't match is left for the following arguments, or is reported as
+/// an excess argument if there aren't any.
+///
+/// ```
+/// let mut greeted = Vec::new();
+/// for input in [&["greet"][..], &["greet", "bob"]] {
+///     conso::parse(input, |ctx| {
+///         ctx.command("greet")
+///             .arg::<Option<String>>()
+///             .run(|name| greeted.push(name.clone()));
+///     });
+/// }
+/// assert_eq!(greeted, [None, Some(String::from("bob"))]);
+/// ```
+///
+/// In the help, it's shown in brackets, like `greet [<string>]`.
+impl<'a, T: Arg<'a>> Arg<'a> for Option<T> {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("[{}]", HelpFmt::render_line(T::help)));
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let checkpoint = input.checkpoint();
+        match T::parse(input) {
+            Some(v) => {
+                Some(Some(v))
+            }
+            None => {
+                input.restore(checkpoint);
+                Some(None)
+            }
+        }
+    }
+}
+
+/// Takes as many values as it can, leaving the input after the last one for the next argument.
+///
+/// ```
+/// let mut given = None;
+/// conso::parse(&["tag", "1", "2", "important"], |ctx| {
+///     ctx.command("tag")
+///         .arg::<Vec<u32>>()
+///         .arg::<String>()
+///         .run(|(ids, tag)| given = Some((ids.clone(), tag.clone())));
+/// });
+/// assert_eq!(given, Some((vec![1, 2], String::from("important"))));
+///
+/// // The unfinished pair `3 x` is put back for the next argument
+/// let mut pairs = None;
+/// conso::parse(&["pairs", "1", "2", "3", "x"], |ctx| {
+///     ctx.command("pairs")
+///         .arg::<Vec<(u32, u32)>>()
+///         .arg::<Vec<String>>()
+///         .run(|(found, rest)| pairs = Some((found.clone(), rest.clone())));
+/// });
+/// assert_eq!(pairs, Some((vec![(1, 2)], vec![String::from("3"), String::from("x")])));
+///
+/// // With nothing after it, what's left over is an error
+/// let excess = conso::test::run(&["sum", "1", "2", "x"], |ctx| {
+///     ctx.command("sum")
+///         .arg::<Vec<u32>>()
+///         .run(|_| {});
+/// });
+/// assert!(excess.output.contains("\nsum 1 2 x\n        ^ Excess arguments passed\n"));
+/// assert!(matches!(excess.state, conso::FinishedState::Error { depth: 3, .. }));
+/// ```
+impl<'a, T: Arg<'a>> Arg<'a> for Vec<T> {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("({})*", HelpFmt::render_line(T::help)));
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let mut vector = Vec::new();
+        loop {
+            // A value that fails to parse may have consumed some of the input anyway
+            let checkpoint = input.checkpoint();
+            match T::parse(input) {
+                // A value that doesn't consume anything would match forever
+                Some(value) if input.depth > checkpoint.depth => vector.push(value),
+                _ => {
+                    input.restore(checkpoint);
+                    return Some(vector);
+                }
+            }
+        }
+    }
+}
+
+/// Exactly `N` values in a row. If one of them is invalid, the error points at that one.
+///
+/// ```
+/// let result = conso::test::run(&["rgb", "10", "300", "20"], |ctx| {
+///     ctx.command("rgb")
+///         .arg::<[u8; 3]>()
+///         .run(|_| {});
+/// });
+/// assert!(result.output.contains("\n       ^^^ Invalid argument"));
+/// ```
+impl<'a, const N: usize, T: Arg<'a>> Arg<'a> for [T; N] {
+    fn help(fmt: &mut HelpFmt) {
+        for _ in 0..N {
+            T::help(fmt);
+        }
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let mut vector = Vec::with_capacity(N);
+        for _ in 0..N {
+            let checkpoint = input.checkpoint();
+            match T::parse(input) {
+                Some(value) => vector.push(value),
+                None => {
+                    input.fail_at(checkpoint);
+                    return None;
+                }
+            }
+        }
+        vector.try_into().ok()
+    }
+}
+
+impl<'a> Arg<'a> for &'a str {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word("<string>");
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        input.next()
+    }
+}
+
+impl<'a> Arg<'a> for String {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word("<string>");
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        input.next().map(String::from)
+    }
+}
+
+/// A range typed as a single segment, like `10..20`. The start can't be after the end.
+impl<'a, T: FromStr + PartialOrd> Arg<'a> for Range<T> {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word("<start..end>");
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let (start, end) = input.next()?.split_once("..")?;
+        let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+        (start <= end).then_some(start..end)
+    }
+}
+
+/// An inclusive range typed as a single segment, like `10..=20`. The start can't be after the end.
+impl<'a, T: FromStr + PartialOrd> Arg<'a> for RangeInclusive<T> {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word("<start..=end>");
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let (start, end) = input.next()?.split_once("..=")?;
+        let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+        (start <= end).then_some(start..=end)
+    }
+}
+
+/// All the remaining segments joined together with spaces. Since it consumes everything,
+/// it has to be the last argument.
+pub struct Rest(pub String);
+
+impl<'a> Arg<'a> for Rest {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word("<text...>");
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let mut text = String::from(input.next()?);
+        while let Some(segment) = input.next() {
+            text.push(' ');
+            text.push_str(segment);
+        }
+        Some(Rest(text))
+    }
+}
+
+/// Like `Rest`, but in a `user_loop` the text is given exactly as it was typed, spacing and all,
+/// which is useful for things like chat messages. Elsewhere the segments are joined with spaces.
+///
+/// Quotes are kept as they were typed too. Global flags and options given after the command are
+/// part of the text as well, since it's everything that was typed.
+///
+/// ```
+/// let input = "chat  hello,   world\n";
+/// let message = conso::user_loop_io(input.as_bytes(), std::io::sink(), |ctx, control_flow| {
+///     ctx.command("chat")
+///         .arg::<conso::RawRest>()
+///         .run(|conso::RawRest(message)| control_flow.quit(message.clone()));
+/// });
+/// assert!(matches!(message, conso::LoopExit::Quit(message) if message == "hello,   world"));
+/// ```
+pub struct RawRest(pub String);
+
+impl<'a> Arg<'a> for RawRest {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word("<text...>");
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let first = input.next()?;
+        let mut segments = vec![first];
+        segments.extend(std::iter::from_fn(|| input.next()));
+        let last = segments[segments.len() - 1];
+        match input.raw_span(first, last) {
+            Some(text) => Some(RawRest(String::from(text))),
+            None => Some(RawRest(segments.join(" "))),
+        }
+    }
+}
+
+/// A single `key=value` segment, split on the first `=`.
+///
+/// ```
+/// let mut settings = Vec::new();
+/// conso::parse(&["set", "name=bob", "query=a=b", "empty="], |ctx| {
+///     ctx.command("set")
+///         .arg::<Vec<conso::KeyValue>>()
+///         .run(|pairs| {
+///             settings = pairs.iter().map(|pair| (pair.key.clone(), pair.value.clone())).collect();
+///         });
+/// });
+/// assert_eq!(settings, [
+///     (String::from("name"), String::from("bob")),
+///     (String::from("query"), String::from("a=b")),
+///     (String::from("empty"), String::new()),
+/// ]);
+///
+/// let missing = conso::test::run(&["set", "novalue"], |ctx| {
+///     ctx.command("set")
+///         .arg::<conso::KeyValue>()
+///         .run(|_| {});
+/// });
+/// assert!(missing.output.contains("\nset novalue\n    ^^^^^^^ Invalid argument, expected <key=value>\n"));
+/// assert!(matches!(missing.state, conso::FinishedState::Error { depth: 1, .. }));
+/// ```
+pub struct KeyValue {
+    pub key: String,
+    pub value: String,
+}
+
+impl<'a> Arg<'a> for KeyValue {
+    fn help(fmt: &mut HelpFmt) {
+        fmt.push_word("<key=value>");
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        let (key, value) = input.next()?.split_once('=')?;
+        Some(KeyValue {
+            key: String::from(key),
+            value: String::from(value),
+        })
+    }
+}
+
+macro_rules! impl_from_str_args {
+    ($($t:ty => $help:literal),* $(,)?) => {
+        $(
+            impl<'a> Arg<'a> for $t {
+                fn help(fmt: &mut HelpFmt) {
+                    fmt.push_word($help);
+                }
+
+                fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+                    input.next().and_then(|v| v.parse().ok())
+                }
+            }
+        )*
+    }
+}
+
+impl_from_str_args!(
+    NonZeroU8 => "<positive integer>",
+    NonZeroU16 => "<positive integer>",
+    NonZeroU32 => "<positive integer>",
+    NonZeroU64 => "<positive integer>",
+    NonZeroU128 => "<positive integer>",
+    NonZeroUsize => "<positive integer>",
+    NonZeroI8 => "<nonzero integer>",
+    NonZeroI16 => "<nonzero integer>",
+    NonZeroI32 => "<nonzero integer>",
+    NonZeroI64 => "<nonzero integer>",
+    NonZeroI128 => "<nonzero integer>",
+    NonZeroIsize => "<nonzero integer>",
+    u8 => "<unsigned integer>",
+    u16 => "<unsigned integer>",
+    u32 => "<unsigned integer>",
+    u64 => "<unsigned integer>",
+    u128 => "<unsigned integer>",
+    usize => "<unsigned integer>",
+    i8 => "<integer>",
+    i16 => "<integer>",
+    i32 => "<integer>",
+    i64 => "<integer>",
+    i128 => "<integer>",
+    isize => "<integer>",
+);
+
+/// Parses the inner integer, for tools doing modular arithmetic.
+///
+/// ```
+/// use std::num::Wrapping;
+///
+/// let mut sum = None;
+/// conso::parse(&["add", "250", "10"], |ctx| {
+///     ctx.command("add")
+///         .arg::<Wrapping<u8>>()
+///         .arg::<Wrapping<u8>>()
+///         .run(|&(a, b)| sum = Some(a + b));
+/// });
+/// assert_eq!(sum, Some(Wrapping(4)));
+/// ```
+impl<'a, T: Arg<'a>> Arg<'a> for Wrapping<T> {
+    fn help(fmt: &mut HelpFmt) {
+        T::help(fmt);
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        T::parse(input).map(Wrapping)
+    }
+}
+
+/// Parses the inner integer, like `Wrapping`.
+impl<'a, T: Arg<'a>> Arg<'a> for Saturating<T> {
+    fn help(fmt: &mut HelpFmt) {
+        T::help(fmt);
+    }
+
+    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
+        T::parse(input).map(Saturating)
+    }
+}
+
+pub trait ConstrainedArg<'a> {
+    type Output;
+
+    fn help(&self, fmt: &mut HelpFmt);
+    fn parse(&self, input: &mut Segments<'a>) -> Option<Self::Output>;
+
+    /// The literal words this constraint matches, if it matches a fixed set of words. For
+    /// several words in a row, this is just the first one. Used for completions, and for warning
+    /// about duplicate command names.
+    fn literals(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    /// All the words this constraint matches in a row, if it's several literal words like a
+    /// `phrase`. Only used for warning about duplicate command names.
+    fn phrase(&self) -> Option<Vec<&str>> {
+        None
+    }
+
+    /// Explains why `remaining` couldn't be parsed, for constraints that can say more than
+    /// what was expected. Called after `parse` failed on the same input.
+    fn error_message(&self, _remaining: &[&str]) -> Option<String> {
+        None
+    }
+
+    /// The value used when this is given to an option that's missing, like with `with_default`.
+    fn default_value(&self) -> Option<Self::Output> {
+        None
+    }
+}
+
+/// Either `a` or `b`, trying `a` first. If neither matches, the error points at where they were
+/// tried and says that either was expected, even when it's part of a bigger constraint. For one
+/// of many words, `one_of` reads better.
+///
+/// ```
+/// let result = conso::test::run(&["seek", "to", "middle"], |ctx| {
+///     ctx.command("seek")
+///         .constrained_arg(("to", conso::either("start", "end")))
+///         .run(|_| {});
+/// });
+/// assert!(result.output.contains("\n        ^^^^^^ Invalid argument, expected [start|end]"));
+/// ```
+pub fn either<A, B>(a: A, b: B) -> Either<A, B> {
+    Either(a, b)
+}
+
+pub struct Either<A, B>(A, B);
+
+impl<'a, A, B> ConstrainedArg<'a> for Either<A, B>
+where
+    A: ConstrainedArg<'a>,
+    B: ConstrainedArg<'a, Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        let Either(a, b) = self;
+        // Rendered as a single word, so that the brackets don't get spaces on the inside
+        fmt.push_word(&format!("[{}|{}]", HelpFmt::render_line(|fmt| a.help(fmt)), HelpFmt::render_line(|fmt| b.help(fmt))));
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        let Either(a, b) = self;
+        let mut literals = a.literals();
+        literals.extend(b.literals());
+        literals
+    }
+
+    fn parse(&self, input: &mut Segments<'a>) -> Option<Self::Output> {
+        let Either(a, b) = self;
+        let checkpoint = input.checkpoint();
+
+        if let Some(result) = a.parse(input) {
+            return Some(result);
+        }
+        input.restore(checkpoint);
+
+        if let Some(result) = b.parse(input) {
+            return Some(result);
+        }
+        input.restore(checkpoint);
+
+        input.fail_at(checkpoint);
+        input.expected = Some(HelpFmt::render_line(|fmt| self.help(fmt)));
+        None
+    }
+}
+
+impl<'a> ConstrainedArg<'a> for String {
+    type Output = ();
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&self);
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        (chunks.next() == Some(self)).then_some(())
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        vec![self]
+    }
+}
+
+impl<'a> ConstrainedArg<'a> for &str {
+    type Output = ();
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&self);
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        (chunks.next() == Some(&self)).then_some(())
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        vec![self]
+    }
+}
+
+impl<'a, T> ConstrainedArg<'a> for Range<T>
+where
+    T: std::fmt::Display + FromStr + PartialOrd,
+{
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("<number {}..{}>", self.start, self.end));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        chunks.next()
+            .and_then(|chunk| chunk.parse().ok())
+            .filter(|v| self.contains(v))
+    }
+}
+
+/// Parses `constraint` as many times as possible, collecting the results.
+///
+/// ```
+/// fn sum(input: &[&str]) -> Option<i32> {
+///     let mut sum = None;
+///     conso::parse(input, |ctx| {
+///         ctx.command("sum")
+///             .constrained_arg(conso::many(0..10))
+///             .run(|numbers| sum = Some(numbers.iter().sum()));
+///     });
+///     sum
+/// }
+///
+/// assert_eq!(sum(&["sum", "1", "2", "3"]), Some(6));
+/// assert_eq!(sum(&["sum"]), Some(0));
+///
+/// // Collecting stops at the first value that doesn't match, which is then left over
+/// let invalid = conso::test::run(&["sum", "1", "x"], |ctx| {
+///     ctx.command("sum")
+///         .constrained_arg(conso::many(0..10))
+///         .run(|_| {});
+/// });
+/// assert!(invalid.output.contains("\nsum 1 x\n      ^ Excess arguments passed\n"));
+/// assert!(matches!(invalid.state, conso::FinishedState::Error { depth: 2, .. }));
+/// ```
+pub fn many<C>(constraint: C) -> Many<C> {
+    Many(constraint)
+}
+
+pub struct Many<C>(C);
+
+impl<'a, C: ConstrainedArg<'a>> ConstrainedArg<'a> for Many<C> {
+    type Output = Vec<C::Output>;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("({})*", HelpFmt::render_line(|fmt| self.0.help(fmt))));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let mut values = Vec::new();
+        loop {
+            let checkpoint = chunks.checkpoint();
+            match self.0.parse(chunks) {
+                // A constraint that doesn't consume anything would match forever
+                Some(value) if chunks.depth > checkpoint.depth => values.push(value),
+                _ => {
+                    chunks.restore(checkpoint);
+                    break Some(values);
+                }
+            }
+        }
+    }
+}
+
+/// A single segment split on `delimiter`, like `foo,bar,baz`. Empty parts are left out.
+///
+/// ```
+/// fn tags(input: &str) -> Option<Vec<String>> {
+///     let mut tags = None;
+///     conso::parse(&["tags", input], |ctx| {
+///         ctx.command("tags")
+///             .constrained_arg(conso::separated_by(','))
+///             .run(|parts| tags = Some(parts.clone()));
+///     });
+///     tags
+/// }
+///
+/// assert_eq!(tags("foo,bar,baz").unwrap(), ["foo", "bar", "baz"]);
+/// assert_eq!(tags("a,,b,").unwrap(), ["a", "b"]);
+/// // Only separators is still a valid, empty list
+/// assert_eq!(tags(",,"), Some(Vec::new()));
+/// ```
+pub fn separated_by(delimiter: char) -> SeparatedBy {
+    SeparatedBy(delimiter)
+}
+
+pub struct SeparatedBy(char);
+
+impl<'a> ConstrainedArg<'a> for SeparatedBy {
+    type Output = Vec<String>;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("<a{0}b{0}c>", self.0));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let chunk = chunks.next()?;
+        Some(chunk.split(self.0).filter(|part| !part.is_empty()).map(String::from).collect())
+    }
+}
+
+/// Matches any of the names in `items`, giving back the value associated with it. Useful when
+/// the names are only known at runtime.
+///
+/// ```
+/// let fruits = vec![(String::from("apple"), 3), (String::from("pear"), 5)];
+///
+/// let mut price = None;
+/// conso::parse(&["pick", "pear"], |ctx| {
+///     ctx.command("pick")
+///         .constrained_arg(conso::choices(fruits.clone()))
+///         .run(|&value| price = Some(value));
+/// });
+/// assert_eq!(price, Some(5));
+///
+/// // The error lists the names that would have worked
+/// let unknown = conso::test::run(&["pick", "grape"], |ctx| {
+///     ctx.command("pick")
+///         .constrained_arg(conso::choices(fruits.clone()))
+///         .run(|_| {});
+/// });
+/// assert!(unknown.output.contains("\npick grape\n     ^^^^^ Invalid argument, expected [apple|pear]\n"));
+/// assert!(matches!(unknown.state, conso::FinishedState::Error { depth: 1, .. }));
+/// ```
+pub fn choices<T: Clone>(items: impl IntoIterator<Item = (String, T)>) -> Choices<T> {
+    Choices(items.into_iter().collect())
+}
+
+pub struct Choices<T>(Vec<(String, T)>);
+
+impl<'a, T: Clone> ConstrainedArg<'a> for Choices<T> {
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        let names = self.0.iter().map(|(name, _)| &**name).collect::<Vec<_>>();
+        fmt.push_word(&format!("[{}]", names.join("|")));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let chunk = chunks.next()?;
+        self.0.iter()
+            .find(|(name, _)| name == chunk)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+/// A segment that matches the regular expression `pattern`, giving back the part of it that
+/// matched. Use `^` and `$` to make the whole segment have to match.
+///
+/// # Panics
+/// If `pattern` isn't a valid regular expression.
+#[cfg(feature = "regex")]
+pub fn regex(pattern: &str) -> RegexArg {
+    match regex::Regex::new(pattern) {
+        Ok(regex) => RegexArg(regex),
+        Err(err) => panic!("Invalid regular expression `{}`: {}", pattern, err),
+    }
+}
+
+#[cfg(feature = "regex")]
+pub struct RegexArg(regex::Regex);
+
+#[cfg(feature = "regex")]
+impl<'a> ConstrainedArg<'a> for RegexArg {
+    type Output = &'a str;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("<{}>", self.0.as_str()));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        self.0.find(chunks.next()?).map(|found| found.as_str())
+    }
+}
+
+/// A JSON value deserialized into `T`, like `{"a":1}`, given as a single segment.
+///
+/// JSON typed into a `user_loop` has to be quoted like `'{"a": 1}'` if it contains any spaces.
+/// Command line arguments are quoted by the shell instead.
+#[cfg(feature = "serde")]
+pub fn json<T: serde::de::DeserializeOwned>() -> JsonArg<T> {
+    JsonArg(std::marker::PhantomData)
+}
+
+#[cfg(feature = "serde")]
+pub struct JsonArg<T>(std::marker::PhantomData<T>);
+
+#[cfg(feature = "serde")]
+impl<'a, T: serde::de::DeserializeOwned> ConstrainedArg<'a> for JsonArg<T> {
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word("<json>");
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        serde_json::from_str(chunks.next()?).ok()
+    }
+}
+
+/// A socket address like `example.com:80` or `127.0.0.1`, where `default_port` is used if no
+/// port is given.
+///
+/// Note that host names are resolved while parsing, which blocks until the DNS lookup is done.
+/// If the name can't be resolved, the argument is invalid. When a name resolves to several
+/// addresses, the first one is used.
+///
+/// ```
+/// use std::net::SocketAddr;
+///
+/// fn connect(input: &str) -> Option<SocketAddr> {
+///     let mut addr = None;
+///     conso::parse(&["connect", input], |ctx| {
+///         ctx.command("connect")
+///             .constrained_arg(conso::host_port(22))
+///             .run(|&target| addr = Some(target));
+///     });
+///     addr
+/// }
+///
+/// assert_eq!(connect("127.0.0.1:8080"), Some("127.0.0.1:8080".parse().unwrap()));
+/// assert_eq!(connect("127.0.0.1"), Some("127.0.0.1:22".parse().unwrap()));
+/// assert_eq!(connect("::1"), Some("[::1]:22".parse().unwrap()));
+///
+/// // A port on its own isn't enough
+/// let no_host = conso::test::run(&["connect", ":80"], |ctx| {
+///     ctx.command("connect")
+///         .constrained_arg(conso::host_port(22))
+///         .run(|_| {});
+/// });
+/// assert!(no_host.output.contains("\nconnect :80\n        ^^^ Invalid argument, expected <host[:port] (default port 22)>\n"));
+/// assert!(matches!(no_host.state, conso::FinishedState::Error { depth: 1, .. }));
+/// ```
+pub fn host_port(default_port: u16) -> HostPort {
+    HostPort(default_port)
+}
+
+pub struct HostPort(u16);
+
+impl<'a> ConstrainedArg<'a> for HostPort {
+    type Output = SocketAddr;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("<host[:port] (default port {})>", self.0));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let chunk = chunks.next()?;
+        if let Ok(addr) = chunk.parse::<SocketAddr>() {
+            return Some(addr);
+        }
+
+        // Checked separately, since ipv6 addresses contain `:` without having a port
+        if let Ok(ip) = chunk.parse::<IpAddr>() {
+            return Some(SocketAddr::new(ip, self.0));
+        }
+
+        let mut addrs = if chunk.contains(':') {
+            chunk.to_socket_addrs().ok()?
+        } else {
+            (chunk, self.0).to_socket_addrs().ok()?
+        };
+        addrs.next()
+    }
+}
+
+/// Any segment except for the `reserved` ones. Useful for telling names the user picked
+/// apart from keywords, like a file name that can't be `help`.
+///
+/// ```
+/// fn open(input: &str) -> Option<String> {
+///     let mut opened = None;
+///     conso::parse(&["open", input], |ctx| {
+///         ctx.command("open")
+///             .constrained_arg(conso::not(&["all", "help"]))
+///             .run(|&name| opened = Some(name.to_string()));
+///     });
+///     opened
+/// }
+///
+/// assert_eq!(open("notes.txt").as_deref(), Some("notes.txt"));
+/// assert_eq!(open("all"), None);
+///
+/// let reserved = conso::test::run(&["open", "all"], |ctx| {
+///     ctx.command("open")
+///         .constrained_arg(conso::not(&["all", "help"]))
+///         .run(|_| {});
+/// });
+/// assert!(reserved.output.contains("\nopen all\n     ^^^ Invalid argument, expected <string>\n"));
+/// assert!(matches!(reserved.state, conso::FinishedState::Error { depth: 1, .. }));
+/// ```
+pub fn not<'r>(reserved: &'r [&'r str]) -> Not<'r> {
+    Not(reserved)
+}
+
+pub struct Not<'r>(&'r [&'r str]);
+
+impl<'a> ConstrainedArg<'a> for Not<'_> {
+    type Output = &'a str;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word("<string>");
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let chunk = chunks.peek().filter(|chunk| !self.0.contains(chunk))?;
+        chunks.next();
+        Some(chunk)
+    }
+}
+
+/// The literal word `name`, giving back a clone of `value` when it matches. Combined with `either`,
+/// this maps keywords to values directly.
+///
+/// ```
+/// #[derive(Clone, Debug, PartialEq)]
+/// enum Speed { Fast, Slow }
+///
+/// let mut speed = None;
+/// conso::parse(&["run", "slow"], |ctx| {
+///     ctx.command("run")
+///         .constrained_arg(conso::either(
+///             conso::literal_value("fast", Speed::Fast),
+///             conso::literal_value("slow", Speed::Slow),
+///         ))
+///         .run(|s| speed = Some(s.clone()));
+/// });
+/// assert_eq!(speed, Some(Speed::Slow));
+/// ```
+pub fn literal_value<T: Clone>(name: &'static str, value: T) -> LiteralValue<T> {
+    LiteralValue(name, value)
+}
+
+pub struct LiteralValue<T>(&'static str, T);
+
+impl<'a, T: Clone> ConstrainedArg<'a> for LiteralValue<T> {
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(self.0);
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        (chunks.next()? == self.0).then(|| self.1.clone())
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        vec![self.0]
+    }
+}
+
+/// Like `one_of`, but doesn't give back which of `names` matched. Reads better than nesting
+/// `either` when there are many of them.
+pub fn aliases<'n>(names: &'n [&'n str]) -> Aliases<'n> {
+    map(one_of(names), |_| ())
+}
+
+pub type Aliases<'n> = Map<OneOf<'n>, fn(&'n str)>;
+
+/// Parses like `inner`, but passes what it gave back through `mapper`. Mostly useful for making
+/// the outputs of the two sides of an `either` the same type.
+///
+/// ```
+/// #[derive(Clone, Debug, PartialEq)]
+/// enum Width {
+///     Auto,
+///     Fixed(u32),
+/// }
+///
+/// let mut width = None;
+/// conso::parse(&["resize", "80"], |ctx| {
+///     ctx.command("resize")
+///         .constrained_arg(conso::either(
+///             conso::literal_value("auto", Width::Auto),
+///             conso::map(1..1000, Width::Fixed),
+///         ))
+///         .run(|w| width = Some(w.clone()));
+/// });
+/// assert_eq!(width, Some(Width::Fixed(80)));
+/// ```
+pub fn map<C, F>(inner: C, mapper: F) -> Map<C, F> {
+    Map(inner, mapper)
+}
+
+pub struct Map<C, F>(C, F);
+
+impl<'a, C, F, U> ConstrainedArg<'a> for Map<C, F>
+where
+    C: ConstrainedArg<'a>,
+    F: Fn(C::Output) -> U,
+{
+    type Output = U;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        self.0.help(fmt);
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        self.0.parse(chunks).map(&self.1)
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        self.0.literals()
+    }
+
+    fn phrase(&self) -> Option<Vec<&str>> {
+        self.0.phrase()
+    }
+
+    fn error_message(&self, remaining: &[&str]) -> Option<String> {
+        self.0.error_message(remaining)
+    }
+
+    fn default_value(&self) -> Option<Self::Output> {
+        self.0.default_value().map(&self.1)
+    }
+}
+
+/// Matches any one of `names`, giving back the one that matched. To give back an enum instead,
+/// see `ConsoEnum` or `choices`.
+///
+/// ```
+/// let mut action = None;
+/// conso::parse(&["service", "restart"], |ctx| {
+///     ctx.command("service")
+///         .constrained_arg(conso::one_of(&["start", "stop", "restart"]))
+///         .run(|&name| action = Some(name));
+/// });
+/// assert_eq!(action, Some("restart"));
+/// ```
+pub fn one_of<'n>(names: &'n [&'n str]) -> OneOf<'n> {
+    OneOf(names)
+}
+
+pub struct OneOf<'n>(&'n [&'n str]);
+
+impl<'a, 'n> ConstrainedArg<'a> for OneOf<'n> {
+    type Output = &'n str;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("[{}]", self.0.join("|")));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let segment = chunks.next()?;
+        self.0.iter().copied().find(|&name| name == segment)
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        self.0.to_vec()
+    }
+}
+
+/// Matches the segments `words` in order, for commands with names of several words.
+///
+/// ```
+/// let mut gave_up = false;
+/// conso::parse(&["give", "up"], |ctx| {
+///     ctx.command(conso::phrase(&["give", "up"]))
+///         .run(|| gave_up = true);
+///     ctx.command("give")
+///         .arg::<String>()
+///         .run(|_| {});
+/// });
+/// assert!(gave_up);
+///
+/// let schema = conso::dump_schema(|ctx| {
+///     ctx.command(conso::phrase(&["give", "up"])).run(|| {});
+/// });
+/// assert_eq!(schema.sub_commands[0].literals, ["give"]);
+/// ```
+pub fn phrase<'w>(words: &'w [&'w str]) -> Phrase<'w> {
+    Phrase(words)
+}
+
+pub struct Phrase<'w>(&'w [&'w str]);
+
+impl<'a> ConstrainedArg<'a> for Phrase<'_> {
+    type Output = ();
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        for word in self.0 {
+            fmt.push_word(word);
+        }
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        self.0.iter().all(|word| chunks.next() == Some(word)).then_some(())
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        self.0.first().copied().into_iter().collect()
+    }
+
+    fn phrase(&self) -> Option<Vec<&str>> {
+        Some(self.0.to_vec())
+    }
+}
+
+/// Parses like `inner`, but gives back `value` if there's nothing left to parse, and shows the
+/// default in the help. Given to `constrained_option`, the default is used when the option is missing.
+///
+/// ```
+/// let mut port = None;
+/// conso::parse(&["serve"], |ctx| {
+///     ctx.command("serve")
+///         .constrained_option("port", conso::with_default(8080, 1..65536))
+///         .run(|&p| port = p);
+/// });
+/// assert_eq!(port, Some(8080));
+///
+/// let help = conso::render_help(|ctx| {
+///     ctx.command("serve")
+///         .constrained_option("port", conso::with_default(8080, 1..65536))
+///         .run(|_| {});
+/// });
+/// assert!(help.contains("[--port <number 1..65536> (default 8080)]"));
+///
+/// let mut port = None;
+/// conso::parse(&["serve"], |ctx| {
+///     ctx.command("serve")
+///         .constrained_option("port", conso::labeled("port", conso::map(conso::with_default(8080, 1..65536), |p| p + 1)))
+///         .run(|&p| port = p);
+/// });
+/// assert_eq!(port, Some(8081));
+/// ```
+pub fn with_default<T, C>(value: T, inner: C) -> WithDefault<T, C> {
+    WithDefault(value, inner)
+}
+
+pub struct WithDefault<T, C>(T, C);
+
+impl<'a, T, C> ConstrainedArg<'a> for WithDefault<T, C>
+where
+    T: Clone + std::fmt::Display,
+    C: ConstrainedArg<'a, Output = T>,
+{
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        self.1.help(fmt);
+        fmt.push_word(&format!("(default {})", self.0));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let empty = chunks.finished();
+        self.1.parse(chunks).or_else(|| empty.then(|| self.0.clone()))
+    }
+
+    fn error_message(&self, remaining: &[&str]) -> Option<String> {
+        self.1.error_message(remaining)
+    }
+
+    fn default_value(&self) -> Option<Self::Output> {
+        Some(self.0.clone())
+    }
+}
+
+/// Parses exactly like `inner`, but shows up as `name=<inner>` in the help, so that the parts of a
+/// tuple can be told apart.
+///
+/// ```
+/// let help = conso::render_help(|ctx| {
+///     ctx.command("multiply")
+///         .constrained_arg((conso::labeled("x", 0..100), conso::labeled("y", 0..100)))
+///         .run(|(x, y)| println!("{}", x * y));
+/// });
+/// assert!(help.contains("multiply x=<number 0..100> y=<number 0..100>"));
+/// ```
+pub fn labeled<C>(name: &str, inner: C) -> Labeled<'_, C> {
+    Labeled(name, inner)
+}
+
+pub struct Labeled<'n, C>(&'n str, C);
+
+impl<'a, C: ConstrainedArg<'a>> ConstrainedArg<'a> for Labeled<'_, C> {
+    type Output = C::Output;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("{}={}", self.0, HelpFmt::render_line(|fmt| self.1.help(fmt))));
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        self.1.parse(chunks)
     }
 
-    pub fn small_indent(&mut self) {
-        self.small_indent += 1;
-        self.line_break();
+    fn literals(&self) -> Vec<&str> {
+        self.1.literals()
     }
 
-    pub fn small_deindent(&mut self) {
-        if self.small_indent != 0 {
-            self.small_indent -= 1;
-        }
-        self.line_break();
+    fn phrase(&self) -> Option<Vec<&str>> {
+        self.1.phrase()
     }
 
-    pub fn push_raw_str(&mut self, string: &str) {
-        if self.empty_line {
-            self.print_indent();
-        }
+    fn error_message(&self, remaining: &[&str]) -> Option<String> {
+        self.1.error_message(remaining)
+    }
 
-        self.push_completely_raw(string);
-        self.current_line_length += self.indent_str.len();
+    fn default_value(&self) -> Option<Self::Output> {
+        self.1.default_value()
     }
+}
 
-    pub fn push_word(&mut self, word: &str) {
-        if !self.empty_line {
-            if self.current_line_length + word.len() > self.max_length {
-                self.line_break();
-            } else {
-                self.push_raw_str(" ");
-            }
-        }
+/// Two values typed as a single segment separated by `sep`, like `3,4` for `pair::<i32>(',')`.
+/// Unlike a tuple, which takes one segment per value, both halves have to be in the same segment.
+///
+/// ```
+/// let mut position = None;
+/// conso::parse(&["goto", "3,-4"], |ctx| {
+///     ctx.command("goto")
+///         .constrained_arg(conso::pair::<i32>(','))
+///         .run(|&pos| position = Some(pos));
+/// });
+/// assert_eq!(position, Some((3, -4)));
+/// ```
+pub fn pair<T: FromStr>(sep: char) -> Pair<T> {
+    Pair(sep, std::marker::PhantomData)
+}
 
-        self.push_raw_str(word);
-    }
+pub struct Pair<T>(char, std::marker::PhantomData<T>);
 
-    pub fn push_paragraph(&mut self, string: &str) {
-        for (i, line) in string.lines().enumerate() {
-            if i > 0 {
-                self.line_break();
-            }
+impl<'a, T: FromStr> ConstrainedArg<'a> for Pair<T> {
+    type Output = (T, T);
 
-            for word in line.split_whitespace() {
-                self.push_word(word);
-            }
-        }
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("<x{}y>", self.0));
     }
 
-    pub fn line_break(&mut self) {
-        if !self.empty_line {
-            self.push_completely_raw("\n");
-            self.empty_line = true;
-            self.current_line_length = 0;
-        }
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let (x, y) = chunks.next()?.split_once(self.0)?;
+        Some((x.parse().ok()?, y.parse().ok()?))
     }
 }
 
-pub struct ControlFlow<'a, T> {
-    result: Option<&'a mut Option<T>>,
+/// Matches if the next segment is `s`, without consuming it. Together with tuples and `either`,
+/// this lets the next segment decide how the rest is parsed.
+///
+/// ```
+/// let mut name = None;
+/// conso::parse(&["open", "all"], |ctx| {
+///     ctx.command("open")
+///         .constrained_arg((conso::peek_is("all"), conso::not(&[])))
+///         .run(|((), n)| name = Some(n.to_string()));
+/// });
+/// assert_eq!(name.as_deref(), Some("all"));
+/// ```
+pub fn peek_is(s: &str) -> PeekIs<'_> {
+    PeekIs(s)
 }
 
-impl<T> ControlFlow<'_, T> {
-    pub fn quit(&mut self, value: T) {
-        if let Some(result) = &mut self.result {
-            **result = Some(value);
-        }
+pub struct PeekIs<'s>(&'s str);
+
+impl<'a> ConstrainedArg<'a> for PeekIs<'_> {
+    type Output = ();
+
+    // Nothing is consumed, so there's nothing to show in the help
+    fn help(&self, _fmt: &mut HelpFmt) {}
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        (chunks.peek() == Some(self.0)).then_some(())
     }
 }
 
-pub trait Arg<'a> {
-    fn help(fmt: &mut HelpFmt);
-    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized;
+/// Matches only if there are no segments left. This lets a command without arguments step
+/// aside for a later one with arguments, instead of failing with excess arguments.
+///
+/// ```
+/// let mut zoom = None;
+/// conso::parse(&["zoom", "3"], |ctx| {
+///     ctx.command(("zoom", conso::end()))
+///         .run(|| zoom = Some(1));
+///     ctx.command("zoom")
+///         .arg::<u32>()
+///         .run(|&level| zoom = Some(level));
+/// });
+/// assert_eq!(zoom, Some(3));
+/// ```
+pub fn end() -> End {
+    End
 }
 
-impl<'a, T: Arg<'a>> Arg<'a> for Option<T> {
-    fn help(fmt: &mut HelpFmt) {
-        fmt.push_word("(");
-        T::help(fmt);
-        fmt.push_word(")?");
+pub struct End;
+
+impl<'a> ConstrainedArg<'a> for End {
+    type Output = ();
+
+    // Nothing is consumed, so there's nothing to show in the help
+    fn help(&self, _fmt: &mut HelpFmt) {}
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        chunks.finished().then_some(())
     }
+}
 
-    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
-        let old_segments = input.clone();
-        match T::parse(input) {
-            Some(v) => {
-                Some(Some(v))
-            }
-            None => {
-                *input = old_segments;
-                Some(None)
-            }
+/// A hex color code like `#1a2b3c`, or the short form `#abc` which is the same as `#aabbcc`,
+/// giving back the red, green and blue components.
+///
+/// ```
+/// let mut background = None;
+/// conso::parse(&["bg", "#1a2b3c"], |ctx| {
+///     ctx.command("bg")
+///         .constrained_arg(conso::color())
+///         .run(|&rgb| background = Some(rgb));
+/// });
+/// assert_eq!(background, Some((0x1a, 0x2b, 0x3c)));
+/// ```
+pub fn color() -> Color {
+    Color
+}
+
+pub struct Color;
+
+impl<'a> ConstrainedArg<'a> for Color {
+    type Output = (u8, u8, u8);
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word("<#rrggbb>");
+    }
+
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let hex = chunks.next()?.strip_prefix('#')?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let component = |digits: &str| u8::from_str_radix(digits, 16).ok();
+        match hex.len() {
+            // Every digit is repeated, so `f` is `ff`
+            3 => Some((component(&hex[0..1])? * 17, component(&hex[1..2])? * 17, component(&hex[2..3])? * 17)),
+            6 => Some((component(&hex[0..2])?, component(&hex[2..4])?, component(&hex[4..6])?)),
+            _ => None,
         }
     }
 }
 
-impl<'a, T: Arg<'a>> Arg<'a> for Vec<T> {
-    fn help(fmt: &mut HelpFmt) {
-        fmt.push_word("(");
-        T::help(fmt);
-        fmt.push_word(")*");
+/// A size in bytes, like `512`, `10MB` or `1.5GiB`. See `Bytes` for the exact format.
+///
+/// ```
+/// fn alloc(input: &str) -> Option<u64> {
+///     let mut size = None;
+///     conso::parse(&["alloc", input], |ctx| {
+///         ctx.command("alloc")
+///             .constrained_arg(conso::bytes())
+///             .run(|&bytes| size = Some(bytes));
+///     });
+///     size
+/// }
+///
+/// assert_eq!(alloc("512"), Some(512));
+/// assert_eq!(alloc("10MB"), Some(10_000_000));
+/// assert_eq!(alloc("1.5GiB"), Some(1_610_612_736));
+/// assert_eq!(alloc("4kib"), Some(4096));
+///
+/// // Sizes that don't fit in a `u64` are invalid, like unknown units
+/// let overflow = conso::test::run(&["alloc", "20000000TB"], |ctx| {
+///     ctx.command("alloc")
+///         .constrained_arg(conso::bytes())
+///         .run(|_| {});
+/// });
+/// assert!(overflow.output.contains("\nalloc 20000000TB\n      ^^^^^^^^^^ Invalid argument, expected <size e.g. 10MB>\n"));
+/// assert!(matches!(overflow.state, conso::FinishedState::Error { depth: 1, .. }));
+/// assert_eq!(alloc("18446744073709551616"), None);
+/// ```
+pub fn bytes() -> Bytes {
+    Bytes
+}
+
+/// Parses a number followed by an optional unit into a number of bytes. Numbers without
+/// a unit (or with the `B` unit) are bytes. The SI units `KB`, `MB`, `GB` and `TB` are powers
+/// of 1000, and the binary units `KiB`, `MiB`, `GiB` and `TiB` are powers of 1024. Units are
+/// case insensitive. Fractional sizes like `1.5GiB` are rounded down to a whole byte.
+pub struct Bytes;
+
+impl<'a> ConstrainedArg<'a> for Bytes {
+    type Output = u64;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word("<size e.g. 10MB>");
     }
 
-    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
-        let vector = std::iter::from_fn(|| T::parse(input)).collect::<Vec<_>>();
-        Some(vector)
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let chunk = chunks.next()?;
+        let unit_start = chunk.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(chunk.len());
+        let (number, unit) = chunk.split_at(unit_start);
+
+        let multiplier: u64 = match &*unit.to_ascii_lowercase() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "mb" => 1000_u64.pow(2),
+            "gb" => 1000_u64.pow(3),
+            "tb" => 1000_u64.pow(4),
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            _ => return None,
+        };
+
+        if number.contains('.') {
+            let bytes = (number.parse::<f64>().ok()? * multiplier as f64).floor();
+            // `u64::MAX as f64` rounds up to 2^64, which is already out of range
+            (bytes.is_finite() && bytes < u64::MAX as f64).then_some(bytes as u64)
+        } else {
+            number.parse::<u64>().ok()?.checked_mul(multiplier)
+        }
     }
 }
 
-impl<'a, const N: usize, T: Arg<'a>> Arg<'a> for [T; N] {
-    fn help(fmt: &mut HelpFmt) {
-        for _ in 0..N {
-            T::help(fmt);
+/// A number followed by a unit, like `10 km` or `10km`, giving back the number and the value
+/// associated with the unit.
+pub fn quantity<'u, U: Clone>(units: &'u [(&'u str, U)]) -> Quantity<'u, U> {
+    Quantity(units)
+}
+
+pub struct Quantity<'u, U>(&'u [(&'u str, U)]);
+
+impl<U> Quantity<'_, U> {
+    /// Splits the input into the number and the name of the unit, which is either the rest of
+    /// the first segment, or the segment after it.
+    fn split<'a>(remaining: &[&'a str]) -> Option<(f64, &'a str, usize)> {
+        let chunk = *remaining.first()?;
+        if let Ok(number) = chunk.parse::<f64>() {
+            return Some((number, *remaining.get(1)?, 2));
         }
+
+        let unit_start = chunk.find(|c: char| c.is_alphabetic())?;
+        let (number, unit) = chunk.split_at(unit_start);
+        Some((number.parse().ok()?, unit, 1))
     }
 
-    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
-        let vector = (0..N).map(|_| T::parse(input)).collect::<Option<Vec<_>>>()?;
-        vector.try_into().ok()
+    fn unit_names(&self) -> String {
+        self.0.iter().map(|(name, _)| *name).collect::<Vec<_>>().join("|")
     }
 }
 
-impl<'a> Arg<'a> for &'a str {
-    fn help(fmt: &mut HelpFmt) {
-        fmt.push_word("<string>");
-    }
+impl<'a, U: Clone> ConstrainedArg<'a> for Quantity<'_, U> {
+    type Output = (f64, U);
 
-    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
-        input.next()
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("<number> [{}]", self.unit_names()));
     }
-}
 
-impl<'a> Arg<'a> for String {
-    fn help(fmt: &mut HelpFmt) {
-        fmt.push_word("<string>");
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let (number, unit, used) = Self::split(&chunks.remaining())?;
+        let (_, value) = self.0.iter().find(|(name, _)| *name == unit)?;
+        for _ in 0..used {
+            chunks.next();
+        }
+        Some((number, value.clone()))
     }
 
-    fn parse(input: &mut Segments<'a>) -> Option<Self> where Self: Sized {
-        input.next().map(String::from)
+    fn error_message(&self, remaining: &[&str]) -> Option<String> {
+        let (_, unit, _) = Self::split(remaining)?;
+        Some(format!("unknown unit `{}`, expected one of [{}]", unit, self.unit_names()))
     }
 }
 
-pub trait ConstrainedArg<'a> {
-    type Output;
+/// An integer that can also be given in hexadecimal, octal or binary, with a `0x`, `0o` or `0b`
+/// prefix, like `0xFF` or `-0b1010`. Integers without a prefix are decimal.
+pub fn radix_int<T: RadixInt>() -> RadixIntArg<T> {
+    RadixIntArg(std::marker::PhantomData)
+}
 
-    fn help(&self, fmt: &mut HelpFmt);
-    fn parse(&self, input: &mut Segments<'a>) -> Option<Self::Output>;
+/// The integer types that `radix_int` can parse.
+pub trait RadixInt: Sized {
+    fn from_str_radix(src: &str, radix: u32) -> Option<Self>;
 }
 
-pub fn either<A, B>(a: A, b: B) -> Either<A, B> {
-    Either(a, b)
+macro_rules! impl_radix_int {
+    ($($t:ty),*) => {
+        $(
+            impl RadixInt for $t {
+                fn from_str_radix(src: &str, radix: u32) -> Option<Self> {
+                    <$t>::from_str_radix(src, radix).ok()
+                }
+            }
+        )*
+    }
 }
 
-pub struct Either<A, B>(A, B);
+impl_radix_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
 
-impl<'a, A, B> ConstrainedArg<'a> for Either<A, B>
-where
-    A: ConstrainedArg<'a>,
-    B: ConstrainedArg<'a, Output = A::Output>,
-{
-    type Output = A::Output;
+pub struct RadixIntArg<T>(std::marker::PhantomData<T>);
+
+impl<'a, T: RadixInt> ConstrainedArg<'a> for RadixIntArg<T> {
+    type Output = T;
 
     fn help(&self, fmt: &mut HelpFmt) {
-        fmt.push_word("[");
-        let Either(a, b) = self;
-        a.help(fmt);
-        fmt.push_word("|");
-        b.help(fmt);
-        fmt.push_word("]");
+        fmt.push_word("<int (0x.. ok)>");
     }
 
-    fn parse(&self, input: &mut Segments<'a>) -> Option<Self::Output> {
-        let Either(a, b) = self;
-
-        {
-            let mut temp = input.clone();
-            if let Some(result) = a.parse(&mut temp) {
-                *input = temp;
-                return Some(result);
-            }
-        }
+    fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
+        let chunk = chunks.next()?;
+        let (sign, unsigned) = match chunk.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", chunk),
+        };
+        let (radix, digits) = match unsigned.get(..2) {
+            Some("0x" | "0X") => (16, &unsigned[2..]),
+            Some("0o" | "0O") => (8, &unsigned[2..]),
+            Some("0b" | "0B") => (2, &unsigned[2..]),
+            _ => (10, unsigned),
+        };
 
-        {
-            let mut temp = input.clone();
-            if let Some(result) = b.parse(&mut temp) {
-                *input = temp;
-                return Some(result);
-            }
+        // `from_str_radix` accepts a sign of its own, which shouldn't be allowed after the prefix
+        if digits.starts_with(['+', '-']) {
+            return None;
         }
-
-        None
+        T::from_str_radix(&format!("{}{}", sign, digits), radix)
     }
 }
 
-impl<'a> ConstrainedArg<'a> for String {
-    type Output = ();
+/// A fraction between 0 and 1, given either as a percentage like `80%`, or directly like `0.8`.
+///
+/// ```
+/// fn volume(input: &str) -> Option<f64> {
+///     let mut volume = None;
+///     conso::parse(&["vol", input], |ctx| {
+///         ctx.command("vol")
+///             .constrained_arg(conso::percentage())
+///             .run(|&fraction| volume = Some(fraction));
+///     });
+///     volume
+/// }
+///
+/// assert_eq!(volume("80%"), Some(0.8));
+/// assert_eq!(volume("0.25"), Some(0.25));
+/// assert_eq!(volume("100%"), Some(1.0));
+/// assert_eq!(volume("150%"), None);
+///
+/// let negative = conso::test::run(&["vol", "-5%"], |ctx| {
+///     ctx.command("vol")
+///         .constrained_arg(conso::percentage())
+///         .run(|_| {});
+/// });
+/// assert!(negative.output.contains("\nvol -5%\n    ^^^ Invalid argument, expected <0-100%>\n"));
+/// assert!(matches!(negative.state, conso::FinishedState::Error { depth: 1, .. }));
+/// ```
+pub fn percentage() -> Percentage {
+    Percentage
+}
+
+pub struct Percentage;
+
+impl<'a> ConstrainedArg<'a> for Percentage {
+    type Output = f64;
 
     fn help(&self, fmt: &mut HelpFmt) {
-        fmt.push_word(&self);
+        fmt.push_word("<0-100%>");
     }
 
     fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
-        (chunks.next() == Some(self)).then_some(())
+        let chunk = chunks.next()?;
+        let fraction = match chunk.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().ok()? / 100.0,
+            None => chunk.parse::<f64>().ok()?,
+        };
+        (0.0..=1.0).contains(&fraction).then_some(fraction)
     }
 }
 
-impl<'a> ConstrainedArg<'a> for &str {
-    type Output = ();
+/// A point in time given as an RFC 3339 timestamp, like `2024-01-02T15:00:00Z`.
+///
+/// The date and the time are both required, separated by a `T`. The time zone is either `Z` for
+/// UTC, or an offset from it like `+01:00`. Fractions of a second like `15:00:00.25Z` are fine,
+/// but leap seconds aren't supported.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+///
+/// let mut times = Vec::new();
+/// for input in ["2024-01-02T15:00:00Z", "2024-01-02T16:00:00+01:00", "2024-02-30T00:00:00Z"] {
+///     conso::parse(&["at", input], |ctx| {
+///         ctx.command("at")
+///             .constrained_arg(conso::timestamp())
+///             .run(|&time| times.push(time));
+///     });
+/// }
+/// let expected = SystemTime::UNIX_EPOCH + Duration::from_secs(1704207600);
+/// assert_eq!(times, [expected, expected]);
+/// ```
+pub fn timestamp() -> Timestamp {
+    Timestamp
+}
+
+pub struct Timestamp;
+
+impl<'a> ConstrainedArg<'a> for Timestamp {
+    type Output = SystemTime;
 
     fn help(&self, fmt: &mut HelpFmt) {
-        fmt.push_word(&self);
+        fmt.push_word("<timestamp>");
     }
 
     fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
-        (chunks.next() == Some(&self)).then_some(())
+        let (date, time) = chunks.next()?.split_once(['T', 't'])?;
+        let [year, month, day] = split_numbers(date, '-', [4, 2, 2])?;
+        let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+            Some(time) => (time, 0),
+            None => {
+                let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+                let [hours, minutes] = split_numbers(&offset[1..], ':', [2, 2])?;
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+                (time, sign * (hours * 3600 + minutes * 60))
+            }
+        };
+        let (time, fraction) = match time.split_once('.') {
+            Some((time, fraction)) => (time, fraction),
+            None => (time, ""),
+        };
+        let [hour, minute, second] = split_numbers(time, ':', [2, 2, 2])?;
+
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+
+        let nanos = match fraction {
+            "" => 0,
+            fraction if fraction.len() <= 9 && fraction.bytes().all(|b| b.is_ascii_digit()) => {
+                u32::from_str(&format!("{:0<9}", fraction)).ok()?
+            }
+            _ => return None,
+        };
+
+        let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+        let since_epoch = Duration::from_secs(seconds.unsigned_abs());
+        let time = if seconds >= 0 {
+            SystemTime::UNIX_EPOCH.checked_add(since_epoch)?
+        } else {
+            SystemTime::UNIX_EPOCH.checked_sub(since_epoch)?
+        };
+        time.checked_add(Duration::from_nanos(nanos.into()))
     }
 }
 
-impl<'a, T> ConstrainedArg<'a> for Range<T>
+/// Splits `string` on `separator` into exactly `N` numbers, each with exactly as many digits as
+/// given in `widths`.
+fn split_numbers<const N: usize>(string: &str, separator: char, widths: [usize; N]) -> Option<[i64; N]> {
+    let mut parts = string.split(separator);
+    let mut numbers = [0; N];
+    for (number, width) in numbers.iter_mut().zip(widths) {
+        let part = parts.next()?;
+        if part.len() != width || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        *number = part.parse().ok()?;
+    }
+    parts.next().is_none().then_some(numbers)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The number of days from 1970-01-01 to the given date, in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Counts years from March, so that the leap day is at the end of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parses `constraint` from the input, and if that fails, from the value of the environment
+/// variable `var` instead. Useful for things like tokens, that are usually set once in the
+/// environment but can be overridden on the command line.
+///
+/// ```
+/// fn login(input: &[&str]) -> Option<String> {
+///     let mut token = None;
+///     conso::parse(input, |ctx| {
+///         ctx.command("login")
+///             .constrained_arg(conso::env_or("CONSO_DOC_TOKEN", conso::unconstrained::<String>()))
+///             .run(|value| token = Some(value.clone()));
+///     });
+///     token
+/// }
+///
+/// // Without the variable, the argument is required
+/// let unset = conso::test::run(&["login"], |ctx| {
+///     ctx.command("login")
+///         .constrained_arg(conso::env_or("CONSO_DOC_TOKEN", conso::unconstrained::<String>()))
+///         .run(|_| {});
+/// });
+/// assert!(unset.output.contains("\nlogin\n      ^ Missing argument, expected <string> (or $CONSO_DOC_TOKEN)\n"));
+/// assert!(matches!(unset.state, conso::FinishedState::Error { depth: 1, .. }));
+///
+/// std::env::set_var("CONSO_DOC_TOKEN", "from-env");
+/// assert_eq!(login(&["login"]).as_deref(), Some("from-env"));
+/// assert_eq!(login(&["login", "given"]).as_deref(), Some("given"));
+/// ```
+pub fn env_or<C>(var: &'static str, constraint: C) -> EnvOr<C> {
+    EnvOr(var, constraint)
+}
+
+pub struct EnvOr<C>(&'static str, C);
+
+impl<'a, C, O> ConstrainedArg<'a> for EnvOr<C>
 where
-    T: std::fmt::Display + FromStr + PartialOrd,
+    C: for<'b> ConstrainedArg<'b, Output = O>,
 {
-    type Output = T;
+    type Output = O;
 
     fn help(&self, fmt: &mut HelpFmt) {
-        fmt.push_word(&format!("<number {}..{}>", self.start, self.end));
+        self.1.help(fmt);
+        fmt.push_word(&format!("(or ${})", self.0));
     }
 
     fn parse(&self, chunks: &mut Segments<'a>) -> Option<Self::Output> {
-        chunks.next()
-            .and_then(|chunk| chunk.parse().ok())
-            .filter(|v| self.contains(v))
+        let checkpoint = chunks.checkpoint();
+        if let Some(value) = self.1.parse(chunks) {
+            return Some(value);
+        }
+        chunks.restore(checkpoint);
+
+        let value = std::env::var(self.0).ok()?;
+        let segments = value.split_whitespace().collect::<Vec<_>>();
+        let mut env_chunks = Segments::detached(&segments);
+        // The whole variable has to be used, otherwise it's probably not what the user meant
+        self.1.parse(&mut env_chunks).filter(|_| env_chunks.finished())
     }
 }
 
@@ -928,6 +5206,143 @@ impl_tuples!(a: A, b: B);
 impl_tuples!(a: A);
 impl_tuples!();
 
+/// An enum whose variants can be picked by name, usually implemented with `#[derive(ConsoEnum)]`
+/// when the `derive` feature is enabled.
+pub trait ConsoEnum: Sized {
+    /// Every name a variant can be picked by, in the order they're shown in the help information.
+    const NAMES: &'static [&'static str];
+
+    fn from_name(name: &str) -> Option<Self>;
+
+    /// A constraint matching any of the variant names, giving back the variant.
+    fn arg() -> EnumArg<Self> {
+        EnumArg(std::marker::PhantomData)
+    }
+}
+
+#[cfg(feature = "derive")]
+pub use conso_derive::{ConsoArgs, ConsoEnum};
+
+pub struct EnumArg<T>(std::marker::PhantomData<T>);
+
+impl<'a, T> ConstrainedArg<'a> for EnumArg<T>
+where
+    T: ConsoEnum,
+{
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("[{}]", T::NAMES.join("|")));
+    }
+
+    fn parse(&self, input: &mut Segments<'a>) -> Option<Self::Output> {
+        input.next().and_then(T::from_name)
+    }
+}
+
+/// A segment converted to a `T` with its `TryFrom<&str>` implementation.
+///
+/// ```
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// enum Color { Red, Green }
+///
+/// impl TryFrom<&str> for Color {
+///     type Error = ();
+///
+///     fn try_from(name: &str) -> Result<Self, ()> {
+///         match name {
+///             "red" => Ok(Color::Red),
+///             "green" => Ok(Color::Green),
+///             _ => Err(()),
+///         }
+///     }
+/// }
+///
+/// let mut painted = None;
+/// conso::parse(&["paint", "green"], |ctx| {
+///     ctx.command("paint")
+///         .constrained_arg(conso::try_from::<Color>())
+///         .run(|&color| painted = Some(color));
+/// });
+/// assert_eq!(painted, Some(Color::Green));
+///
+/// // The placeholder is the name of the type
+/// let unknown = conso::test::run(&["paint", "blue"], |ctx| {
+///     ctx.command("paint")
+///         .constrained_arg(conso::try_from::<Color>())
+///         .run(|_| {});
+/// });
+/// assert!(unknown.output.contains("\npaint blue\n      ^^^^ Invalid argument, expected <Color>\n"));
+/// assert!(matches!(unknown.state, conso::FinishedState::Error { depth: 1, .. }));
+/// ```
+pub fn try_from<T>() -> TryFromArg<T> {
+    TryFromArg(std::marker::PhantomData)
+}
+
+pub struct TryFromArg<T>(std::marker::PhantomData<T>);
+
+impl<'a, T> ConstrainedArg<'a> for TryFromArg<T>
+where
+    T: TryFrom<&'a str>,
+{
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(&format!("<{}>", short_type_name::<T>()));
+    }
+
+    fn parse(&self, input: &mut Segments<'a>) -> Option<Self::Output> {
+        T::try_from(input.next()?).ok()
+    }
+}
+
+/// A segment parsed as a `T` with its `FromStr` implementation, where `placeholder` is shown in the
+/// help, e.g. `<color>`.
+///
+/// ```
+/// use std::net::Ipv4Addr;
+///
+/// let mut loaded = None;
+/// conso::parse(&["load", "127.0.0.1"], |ctx| {
+///     ctx.command("load")
+///         .constrained_arg(conso::from_str::<Ipv4Addr>("<ip>"))
+///         .run(|&ip| loaded = Some(ip));
+/// });
+/// assert_eq!(loaded, Some(Ipv4Addr::LOCALHOST));
+///
+/// let invalid = conso::test::run(&["load", "x"], |ctx| {
+///     ctx.command("load")
+///         .constrained_arg(conso::from_str::<Ipv4Addr>("<ip>"))
+///         .run(|_| {});
+/// });
+/// assert!(invalid.output.contains("\nload x\n     ^ Invalid argument, expected <ip>\n"));
+/// assert!(matches!(invalid.state, conso::FinishedState::Error { depth: 1, .. }));
+/// ```
+pub fn from_str<T: FromStr>(placeholder: &'static str) -> FromStrArg<T> {
+    FromStrArg(placeholder, std::marker::PhantomData)
+}
+
+pub struct FromStrArg<T>(&'static str, std::marker::PhantomData<T>);
+
+impl<'a, T: FromStr> ConstrainedArg<'a> for FromStrArg<T> {
+    type Output = T;
+
+    fn help(&self, fmt: &mut HelpFmt) {
+        fmt.push_word(self.0);
+    }
+
+    fn parse(&self, input: &mut Segments<'a>) -> Option<Self::Output> {
+        input.next()?.parse().ok()
+    }
+}
+
+/// The name of a type without the module path, e.g. `Color` instead of `my_crate::Color`.
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let path = &name[..name.find('<').unwrap_or(name.len())];
+    &name[path.rfind("::").map_or(0, |i| i + 2)..]
+}
+
 pub struct Unconstrained<T>(std::marker::PhantomData<T>);
 
 pub fn unconstrained<T>() -> Unconstrained<T> {